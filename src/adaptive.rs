@@ -0,0 +1,143 @@
+//! Adaptive sampling: spending samples where the image is still noisy.
+//!
+//! Every pixel keeps a running mean of its samples and the variance of their
+//! luminance (Welford's algorithm). Once a pixel has its minimum number of
+//! samples, it only receives more while the estimated relative error of its
+//! mean exceeds the noise threshold. The render is done when no pixel needs
+//! more samples.
+
+use glam::Vec3;
+
+use crate::aov::Features;
+use crate::tonemap::luminance;
+
+/// Options of [`crate::renderer::draw_adaptive_frame`].
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveSettings {
+    /// Relative standard error of a pixel's mean luminance below which it
+    /// stops receiving samples. 0 keeps sampling every pixel uniformly.
+    pub noise_threshold: f32,
+    /// Samples every pixel receives before its error estimate is trusted.
+    pub min_samples: u32,
+    /// Samples after which a pixel counts as converged whatever its error.
+    pub max_samples: u32,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            noise_threshold: 0.0,
+            min_samples: 16,
+            max_samples: u32::MAX,
+        }
+    }
+}
+
+/// Sample statistics of one pixel.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PixelStats {
+    pub mean: Vec3,
+    /// Sum of squared deviations of the sample luminances from their mean.
+    m2: f32,
+    pub sample_count: u32,
+    /// Running mean of the first hit features, for the denoiser.
+    pub features: Features,
+}
+
+impl PixelStats {
+    pub fn add_sample(&mut self, color: Vec3) {
+        let value = luminance(color);
+        let old_mean = luminance(self.mean);
+        self.sample_count += 1;
+        self.mean += (color - self.mean) / self.sample_count as f32;
+        self.m2 += (value - old_mean) * (value - luminance(self.mean));
+    }
+
+    /// Unbiased variance of the sample luminances.
+    pub fn variance(&self) -> f32 {
+        if self.sample_count < 2 {
+            return 0.0;
+        }
+        self.m2.max(0.0) / (self.sample_count - 1) as f32
+    }
+
+    /// Standard error of the mean luminance relative to the mean itself.
+    /// Dark pixels are measured against a floor, so that black regions with
+    /// a few fireflies do not draw samples forever.
+    pub fn relative_error(&self) -> f32 {
+        const LUMINANCE_FLOOR: f32 = 1e-3;
+        if self.sample_count == 0 {
+            return f32::INFINITY;
+        }
+        let standard_error = (self.variance() / self.sample_count as f32).sqrt();
+        standard_error / luminance(self.mean).abs().max(LUMINANCE_FLOOR)
+    }
+
+    /// Whether the pixel should receive more samples.
+    pub fn is_active(&self, settings: &AdaptiveSettings) -> bool {
+        if self.sample_count >= settings.max_samples {
+            return false;
+        }
+        self.sample_count < settings.min_samples
+            || settings.noise_threshold <= 0.0
+            || self.relative_error() > settings.noise_threshold
+    }
+}
+
+/// Per pixel statistics of an image rendered with adaptive sampling.
+#[derive(Clone, Debug)]
+pub struct AdaptiveBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<PixelStats>,
+}
+
+impl AdaptiveBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        AdaptiveBuffer {
+            width,
+            height,
+            pixels: vec![PixelStats::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pixels.fill(PixelStats::default());
+    }
+
+    /// Average radiance per pixel.
+    pub fn radiance(&self) -> Vec<[f32; 3]> {
+        self.pixels.iter().map(|px| px.mean.to_array()).collect()
+    }
+
+    /// Number of pixels whose relative error exceeds `noise_threshold`,
+    /// including those that stopped at the maximum number of samples.
+    pub fn pixels_above(&self, noise_threshold: f32) -> usize {
+        self.pixels
+            .iter()
+            .filter(|px| px.relative_error() > noise_threshold)
+            .count()
+    }
+
+    /// First hit features per pixel.
+    pub fn features(&self) -> Vec<Features> {
+        self.pixels.iter().map(|px| px.features).collect()
+    }
+
+    /// Mean number of samples per pixel.
+    pub fn mean_samples(&self) -> f32 {
+        let total: u64 = self.pixels.iter().map(|px| px.sample_count as u64).sum();
+        total as f32 / self.pixels.len().max(1) as f32
+    }
+
+    /// Mean relative error of the pixels, an estimate of the noise level of
+    /// the whole image.
+    pub fn mean_relative_error(&self) -> f32 {
+        let total: f64 = self
+            .pixels
+            .iter()
+            .map(|px| px.relative_error().min(1.0) as f64)
+            .sum();
+        (total / self.pixels.len().max(1) as f64) as f32
+    }
+}
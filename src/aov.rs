@@ -0,0 +1,133 @@
+//! Arbitrary output variables: per pixel properties of the first hit of the
+//! camera rays, rendered in the same pass as the image.
+//!
+//! The renderer records [`Features`] for every sample and keeps their running
+//! mean per pixel. They guide the denoiser and can be written next to the
+//! image as extra layers of an OpenEXR file for compositing.
+
+use glam::Vec3;
+
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::types::{Hit, Ray};
+
+/// Properties of the first hit of a camera ray. Misses have all values zero
+/// and no indices.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Features {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    /// Distance of the hit in front of the camera along its view
+    /// direction.
+    pub depth: f32,
+    /// Hit point in world space.
+    pub position: Vec3,
+    /// Index of the hit top-level shape of the scene.
+    pub shape_index: Option<usize>,
+    /// Index of the hit material, see [`Bvh::material_index`].
+    pub material_index: Option<usize>,
+}
+
+impl Features {
+    pub fn from_hit(ray: &Ray, hit: Option<&Hit>, camera: &Camera, scene: &Bvh) -> Self {
+        hit.map_or(Features::default(), |hit| Features {
+            albedo: hit.material.color,
+            normal: hit.normal,
+            depth: (hit.point(ray) - camera.pos).dot(camera.look_dir),
+            position: hit.point(ray),
+            shape_index: Some(hit.shape_index),
+            material_index: Some(scene.material_index(hit.shape_index)),
+        })
+    }
+
+    /// Adds `sample` to the running mean of the features of `count`
+    /// previous samples. Indices cannot be averaged, so they come from the
+    /// first sample.
+    pub fn accumulate(&mut self, sample: &Features, count: u32) {
+        if count == 0 {
+            *self = *sample;
+            return;
+        }
+        let weight = 1.0 / (count + 1) as f32;
+        self.albedo += (sample.albedo - self.albedo) * weight;
+        self.normal += (sample.normal - self.normal) * weight;
+        self.depth += (sample.depth - self.depth) * weight;
+        self.position += (sample.position - self.position) * weight;
+    }
+}
+
+/// An output variable, stored as a layer of one or three channels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// View space depth: distance from the camera along its view direction,
+    /// 0 where nothing is hit.
+    Depth,
+    /// World space surface normal.
+    Normal,
+    /// Surface color.
+    Albedo,
+    /// Index of the shape in the scene, -1 where nothing is hit.
+    ShapeIndex,
+    /// Index of the material, -1 where nothing is hit.
+    MaterialIndex,
+    /// World space hit point.
+    Position,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ShapeIndex,
+        Aov::MaterialIndex,
+        Aov::Position,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "depth" => Aov::Depth,
+            "normal" => Aov::Normal,
+            "albedo" => Aov::Albedo,
+            "shape_index" => Aov::ShapeIndex,
+            "material_index" => Aov::MaterialIndex,
+            "position" => Aov::Position,
+            _ => return None,
+        })
+    }
+
+    /// Name of the layer.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ShapeIndex => "shape_index",
+            Aov::MaterialIndex => "material_index",
+            Aov::Position => "position",
+        }
+    }
+
+    /// Names of the channels of the layer.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::ShapeIndex | Aov::MaterialIndex => &["id"],
+        }
+    }
+
+    /// Values of the channels for a pixel with `features`.
+    pub fn values(&self, features: &Features) -> [f32; 3] {
+        let index = |i: Option<usize>| [i.map_or(-1.0, |i| i as f32), 0.0, 0.0];
+        match self {
+            Aov::Depth => [features.depth, 0.0, 0.0],
+            Aov::Normal => features.normal.to_array(),
+            Aov::Albedo => features.albedo.to_array(),
+            Aov::ShapeIndex => index(features.shape_index),
+            Aov::MaterialIndex => index(features.material_index),
+            Aov::Position => features.position.to_array(),
+        }
+    }
+}
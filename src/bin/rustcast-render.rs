@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rustcast::adaptive::{AdaptiveBuffer, AdaptiveSettings};
+use rustcast::aov::{Aov, Features};
+use rustcast::bvh::Bvh;
+use rustcast::denoise::{DenoiseSettings, denoise};
+use rustcast::filter::Filter;
+use rustcast::output::{
+    BitDepth, ImageFormat, ImageMetadata, average_radiance, write_exr, write_image,
+};
+use rustcast::renderer::{RenderMode, RenderSettings, draw_adaptive_frame, draw_frame};
+use rustcast::sampler::SamplerKind;
+use rustcast::scene_file::load_scene_file;
+use rustcast::scenes::{load_scene, scene_index};
+use rustcast::tonemap::{ToneMapOperator, ToneMapSettings};
+
+const USAGE: &str = "\
+usage: rustcast-render [options] -o <output.png|.ppm|.pfm|.exr>
+
+options:
+  --scene <name>     default, cornell, axes, cylinder, 0-3 or a scene file path
+                     (default: cornell)
+  --mode <mode>      raycast, raytrace, normals, pathtracing (default: pathtracing)
+  --width <px>       image width (default: 512)
+  --height <px>      image height (default: 512)
+  --samples <n>      minimum number of samples per pixel (default: 100)
+  --noise <error>    adaptive sampling: keep sampling pixels whose relative
+                     standard error exceeds <error>, e.g. 0.01
+  --max-samples <n>  samples per pixel at which adaptive sampling stops
+                     (default: 10 times --samples)
+  --time-limit <s>   stop sampling after <s> seconds, even if the sample
+                     count or noise target is not reached
+  --denoise          filter the noise out of the image, guided by the albedo,
+                     normals and depth of the first hits
+  --max-depth <n>    reflection depth in raytrace mode (default: 5)
+  --filter <name>    pixel filter: box, tent, gaussian, mitchell (default: box)
+  --sampler <name>   independent, stratified, halton, sobol (default: independent)
+  --seed <n>         random seed, equal seeds give identical images (default: 0)
+  --max-path-depth <n>
+                     maximum bounces in pathtracing mode (default: 32)
+  --tonemap <name>   linear, reinhard, extended-reinhard, aces, hable
+                     (default: aces)
+  --exposure <ev|auto>
+                     exposure in stops, or automatic (default: auto)
+  --bit-depth <8|16>  bits per channel of PNG and PPM output (default: 8)
+  --aov <names>      extra EXR layers, comma separated or 'all': depth,
+                     normal, albedo, shape_index, material_index, position
+  -o, --output <path>  output file, format chosen by extension; PFM and EXR
+                     store linear radiance as 32-bit floats";
+
+struct RenderArgs {
+    scene: String,
+    settings: RenderSettings,
+    width: u32,
+    height: u32,
+    samples: u32,
+    /// Noise threshold and maximum samples per pixel of adaptive sampling.
+    noise: Option<f32>,
+    max_samples: Option<u32>,
+    time_limit: Option<Duration>,
+    denoise: bool,
+    aovs: Vec<Aov>,
+    output: PathBuf,
+    format: ImageFormat,
+    bit_depth: BitDepth,
+    tone_mapping: ToneMapSettings,
+}
+
+fn parse_args(args: &[String]) -> Result<RenderArgs, String> {
+    let mut scene = "cornell".to_string();
+    let mut settings = RenderSettings {
+        render_mode: RenderMode::Pathtracing,
+        ..RenderSettings::default()
+    };
+    let mut width = 512;
+    let mut height = 512;
+    let mut samples = 100;
+    let mut noise = None;
+    let mut max_samples = None;
+    let mut time_limit = None;
+    let mut denoise = false;
+    let mut aovs = Vec::new();
+    let mut output = None;
+    let mut bit_depth = BitDepth::Eight;
+    let mut tone_mapping = ToneMapSettings::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--scene" => scene = value()?.clone(),
+            "--mode" => {
+                settings.render_mode = match value()?.as_str() {
+                    "raycast" => RenderMode::Raycast,
+                    "raytrace" => RenderMode::Raytrace,
+                    "normals" => RenderMode::Normals,
+                    "pathtracing" => RenderMode::Pathtracing,
+                    other => return Err(format!("unknown render mode '{}'", other)),
+                }
+            }
+            "--filter" => {
+                let name = value()?;
+                settings.filter =
+                    Filter::from_name(name).ok_or_else(|| format!("unknown filter '{}'", name))?;
+            }
+            "--width" => width = parse_number(arg, value()?)?,
+            "--height" => height = parse_number(arg, value()?)?,
+            "--samples" => samples = parse_number(arg, value()?)?,
+            "--noise" => noise = Some(parse_number(arg, value()?)?),
+            "--max-samples" => max_samples = Some(parse_number(arg, value()?)?),
+            "--time-limit" => {
+                let seconds: f64 = parse_number(arg, value()?)?;
+                time_limit = Some(
+                    Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("invalid value '{}' for {}", seconds, arg))?,
+                );
+            }
+            "--denoise" => denoise = true,
+            "--max-depth" => settings.max_ray_depth = parse_number(arg, value()?)?,
+            "--sampler" => {
+                let name = value()?;
+                settings.sampler = SamplerKind::from_name(name)
+                    .ok_or_else(|| format!("unknown sampler '{}'", name))?;
+            }
+            "--seed" => settings.seed = parse_number(arg, value()?)?,
+            "--max-path-depth" => settings.max_path_depth = parse_number(arg, value()?)?,
+            "--bit-depth" => {
+                bit_depth = match value()?.as_str() {
+                    "8" => BitDepth::Eight,
+                    "16" => BitDepth::Sixteen,
+                    other => return Err(format!("unsupported bit depth '{}'", other)),
+                }
+            }
+            "--tonemap" => {
+                let name = value()?;
+                tone_mapping.operator = ToneMapOperator::from_name(name)
+                    .ok_or_else(|| format!("unknown tone mapping operator '{}'", name))?;
+            }
+            "--exposure" => match value()?.as_str() {
+                "auto" => tone_mapping.auto_exposure = true,
+                stops => {
+                    tone_mapping.auto_exposure = false;
+                    tone_mapping.exposure = parse_number(arg, stops)?;
+                }
+            },
+            "--aov" => {
+                aovs = match value()?.as_str() {
+                    "all" => Aov::ALL.to_vec(),
+                    names => names
+                        .split(',')
+                        .map(|name| {
+                            Aov::from_name(name).ok_or_else(|| format!("unknown AOV '{}'", name))
+                        })
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument '{}'\n\n{}", other, USAGE)),
+        }
+    }
+
+    let output = output.ok_or_else(|| format!("missing output path\n\n{}", USAGE))?;
+    let format = ImageFormat::from_path(&output)
+        .ok_or_else(|| format!("unsupported output format '{}'", output.display()))?;
+    if !aovs.is_empty() && format != ImageFormat::Exr {
+        return Err("AOVs can only be written to EXR files".to_string());
+    }
+    if width == 0 || height == 0 {
+        return Err("width and height must be positive".to_string());
+    }
+
+    Ok(RenderArgs {
+        scene,
+        settings,
+        width,
+        height,
+        samples,
+        noise,
+        max_samples,
+        time_limit,
+        denoise,
+        aovs,
+        output,
+        format,
+        bit_depth,
+        tone_mapping,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, arg))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Renders a scene without opening a window and writes the result to disk.
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args(args)?;
+    let (mut camera, lights, shapes) = match scene_index(&args.scene) {
+        Some(index) => load_scene(index),
+        None => load_scene_file(Path::new(&args.scene))?,
+    };
+    camera.set_film_size(args.width, args.height);
+    let bvh = Bvh::new(shapes);
+
+    let start = Instant::now();
+    let out_of_time = || {
+        args.time_limit
+            .is_some_and(|limit| start.elapsed() >= limit)
+    };
+    let (radiance, features, samples) = match args.noise {
+        Some(noise_threshold) => {
+            let min_samples = args.samples.max(1);
+            let adaptive = AdaptiveSettings {
+                noise_threshold,
+                min_samples,
+                max_samples: args
+                    .max_samples
+                    .unwrap_or(args.samples.saturating_mul(10))
+                    .max(min_samples),
+            };
+            let mut buffer = AdaptiveBuffer::new(args.width, args.height);
+            while !out_of_time()
+                && draw_adaptive_frame(
+                    &mut buffer,
+                    &args.settings,
+                    &adaptive,
+                    &camera,
+                    &lights,
+                    &bvh,
+                ) > 0
+            {}
+            println!(
+                "Mean relative error {:.4}, {} pixels above the threshold",
+                buffer.mean_relative_error(),
+                buffer.pixels_above(adaptive.noise_threshold)
+            );
+            (
+                buffer.radiance(),
+                buffer.features(),
+                buffer.mean_samples().round() as u32,
+            )
+        }
+        None => {
+            let pixel_count = (args.width * args.height) as usize;
+            let mut frame_buffer = vec![0.0; 3 * pixel_count];
+            let mut features = vec![Features::default(); pixel_count];
+            let mut sample_count = 0;
+            while sample_count < args.samples.max(1) && !out_of_time() {
+                sample_count += draw_frame(
+                    &mut frame_buffer,
+                    Some(&mut features),
+                    args.width,
+                    args.height,
+                    sample_count,
+                    &args.settings,
+                    &camera,
+                    &lights,
+                    &bvh,
+                );
+            }
+            (
+                average_radiance(&frame_buffer, pixel_count, sample_count),
+                features,
+                sample_count,
+            )
+        }
+    };
+    let render_time = start.elapsed();
+    println!(
+        "Render time: {:9.3} ms. {} samples per pixel on average",
+        render_time.as_secs_f64() * 1000.0,
+        samples
+    );
+
+    let radiance = if args.denoise {
+        let start = Instant::now();
+        let denoised = denoise(
+            &radiance,
+            &features,
+            args.width,
+            args.height,
+            &DenoiseSettings::default(),
+        );
+        println!(
+            "Denoise time: {:9.3} ms",
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+        denoised
+    } else {
+        radiance
+    };
+
+    let metadata = ImageMetadata {
+        scene: args.scene.clone(),
+        samples,
+        render_time,
+    };
+    if args.aovs.is_empty() {
+        write_image(
+            &args.output,
+            args.format,
+            args.bit_depth,
+            args.width,
+            args.height,
+            &radiance,
+            &args.tone_mapping,
+            &metadata,
+        )?;
+    } else {
+        write_exr(
+            &args.output,
+            args.width,
+            args.height,
+            &radiance,
+            &features,
+            &args.aovs,
+            &metadata,
+        )?;
+    }
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Reflectance model of opaque surfaces in the path tracer: a Lambertian
+//! lobe tinted by the material color plus a white, energy normalized Phong
+//! lobe weighted by `specular_coef` with exponent `shininess`.
+
+use glam::{Vec2, Vec3};
+use std::f32::consts::{FRAC_1_PI, TAU};
+
+use crate::sampling::{orthonormal_basis, sample_cosine_hemisphere};
+use crate::types::Material;
+
+/// The BSDF at one surface point, seen from one direction.
+pub struct Bsdf {
+    color: Vec3,
+    specular: f32,
+    shininess: f32,
+    /// Surface normal on the side of the viewer.
+    normal: Vec3,
+    /// Mirror direction of the incoming ray, the axis of the Phong lobe.
+    mirror: Vec3,
+}
+
+impl Bsdf {
+    /// BSDF for a ray travelling along `direction` and hitting a surface with
+    /// unit normal `normal`.
+    pub fn new(material: &Material, normal: Vec3, direction: Vec3) -> Self {
+        let normal = if direction.dot(normal) > 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        Bsdf {
+            color: material.color,
+            specular: material.specular_coef.clamp(0.0, 1.0),
+            shininess: material.shininess.max(0.0),
+            normal,
+            mirror: direction - 2.0 * direction.dot(normal) * normal,
+        }
+    }
+
+    /// Surface normal on the side of the incoming ray.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Reflected fraction of light arriving from `wi`, per unit solid angle
+    /// and projected area.
+    pub fn eval(&self, wi: Vec3) -> Vec3 {
+        if wi.dot(self.normal) <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let diffuse = (1.0 - self.specular) * FRAC_1_PI * self.color;
+        if self.specular <= 0.0 {
+            return diffuse;
+        }
+        let cos_alpha = wi.dot(self.mirror).max(0.0);
+        let glossy = (self.shininess + 2.0) / TAU * cos_alpha.powf(self.shininess);
+        diffuse + Vec3::splat(self.specular * glossy)
+    }
+
+    /// Solid angle density with which [`Bsdf::sample`] returns `wi`.
+    pub fn pdf(&self, wi: Vec3) -> f32 {
+        let cos_theta = wi.dot(self.normal);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        let diffuse = cos_theta * FRAC_1_PI;
+        if self.specular <= 0.0 {
+            return diffuse;
+        }
+        let cos_alpha = wi.dot(self.mirror).max(0.0);
+        let glossy = (self.shininess + 1.0) / TAU * cos_alpha.powf(self.shininess);
+        (1.0 - self.specular) * diffuse + self.specular * glossy
+    }
+
+    /// Samples a direction, choosing the lobe with `u_lobe`. Returns `None`
+    /// when the glossy lobe points below the surface.
+    pub fn sample(&self, u_lobe: f32, u: Vec2) -> Option<Vec3> {
+        if u_lobe >= self.specular {
+            return Some(sample_cosine_hemisphere(u, self.normal));
+        }
+        let cos_alpha = u.x.powf(1.0 / (self.shininess + 1.0));
+        let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+        let phi = TAU * u.y;
+        let (t, b) = orthonormal_basis(self.mirror);
+        let wi = sin_alpha * (phi.cos() * t + phi.sin() * b) + cos_alpha * self.mirror;
+        (wi.dot(self.normal) > 0.0).then_some(wi)
+    }
+}
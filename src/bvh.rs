@@ -0,0 +1,324 @@
+//! Bounding volume hierarchy over scene shapes.
+//!
+//! The tree is built top-down with a binned surface area heuristic (SAH) and
+//! stored as a flat array in depth-first order, so the left child of an inner
+//! node always directly follows it.
+
+use glam::Vec3;
+
+use crate::shape::Shape;
+use crate::types::{Aabb, Hit, Material, Ray};
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+/// Deeper nodes become leaves so traversal fits in a fixed size stack.
+const MAX_DEPTH: usize = 60;
+/// Cost of a ray/box test relative to a primitive intersection.
+const TRAVERSAL_COST: f32 = 0.125;
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    /// First primitive for leaves, index of the right child for inner nodes.
+    offset: u32,
+    /// Number of primitives, zero for inner nodes.
+    count: u32,
+    /// Split axis of inner nodes, used to visit the nearer child first.
+    axis: u8,
+}
+
+/// Hierarchy over a set of primitives identified by their index.
+#[derive(Clone, Debug, Default)]
+pub struct BvhTree {
+    nodes: Vec<BvhNode>,
+    indices: Vec<u32>,
+}
+
+impl BvhTree {
+    /// Builds the tree over primitives with the given bounds.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut tree = BvhTree {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len() as u32).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Vec3> = bounds.iter().map(Aabb::centroid).collect();
+            tree.build_node(bounds, &centroids, 0, bounds.len(), 0);
+        }
+        tree
+    }
+
+    fn build_node(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) {
+        let node_idx = self.nodes.len();
+        let prims = &self.indices[start..end];
+        let node_bounds = prims
+            .iter()
+            .fold(Aabb::EMPTY, |acc, &i| acc.union(&bounds[i as usize]));
+        self.nodes.push(BvhNode {
+            bounds: node_bounds,
+            offset: start as u32,
+            count: (end - start) as u32,
+            axis: 0,
+        });
+
+        let count = end - start;
+        if count <= 1 || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let centroid_bounds = prims
+            .iter()
+            .fold(Aabb::EMPTY, |acc, &i| acc.grow(centroids[i as usize]));
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = extent.max_position();
+        if extent[axis] <= f32::EPSILON {
+            return;
+        }
+
+        // Bin primitives by centroid along the widest axis.
+        let bin_of = |i: u32| {
+            let rel = (centroids[i as usize][axis] - centroid_bounds.min[axis]) / extent[axis];
+            ((rel * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+        };
+        let mut bin_bounds = [Aabb::EMPTY; BIN_COUNT];
+        let mut bin_counts = [0usize; BIN_COUNT];
+        for &i in prims {
+            let b = bin_of(i);
+            bin_bounds[b] = bin_bounds[b].union(&bounds[i as usize]);
+            bin_counts[b] += 1;
+        }
+
+        // Sweep from the right to get the cost of every split plane.
+        let mut right_area = [0.0f32; BIN_COUNT];
+        let mut acc = Aabb::EMPTY;
+        for b in (1..BIN_COUNT).rev() {
+            acc = acc.union(&bin_bounds[b]);
+            right_area[b] = acc.surface_area();
+        }
+        let mut best = (f32::INFINITY, 0);
+        let (mut left, mut left_count) = (Aabb::EMPTY, 0);
+        for b in 0..BIN_COUNT - 1 {
+            left = left.union(&bin_bounds[b]);
+            left_count += bin_counts[b];
+            let right_count = count - left_count;
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost =
+                left.surface_area() * left_count as f32 + right_area[b + 1] * right_count as f32;
+            if cost < best.0 {
+                best = (cost, b);
+            }
+        }
+
+        let leaf_cost = count as f32;
+        let split_cost = TRAVERSAL_COST + best.0 / node_bounds.surface_area().max(f32::EPSILON);
+        if best.0.is_infinite() || (count <= MAX_LEAF_SIZE && split_cost >= leaf_cost) {
+            return;
+        }
+
+        let split = best.1;
+        let mid = start + partition(&mut self.indices[start..end], |&i| bin_of(i) <= split);
+
+        self.nodes[node_idx].count = 0;
+        self.nodes[node_idx].axis = axis as u8;
+        self.build_node(bounds, centroids, start, mid, depth + 1);
+        self.nodes[node_idx].offset = self.nodes.len() as u32;
+        self.build_node(bounds, centroids, mid, end, depth + 1);
+    }
+
+    /// Visits the primitives whose leaves the ray reaches before `t_max`.
+    ///
+    /// `intersect` is called with a primitive index and the current maximum
+    /// distance and returns the distance of a closer hit, if any, which then
+    /// shrinks the search range. With `any_hit` the traversal stops at the
+    /// first reported hit. Returns whether any hit was reported.
+    pub fn traverse(
+        &self,
+        ray: &Ray,
+        mut t_max: f32,
+        any_hit: bool,
+        mut intersect: impl FnMut(usize, f32) -> Option<f32>,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inv_dir = ray.direction.recip();
+        let mut found = false;
+        let mut stack = [0u32; 64];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_idx = stack[stack_len];
+            let node = &self.nodes[node_idx as usize];
+            if node.bounds.intersect(ray, inv_dir, t_max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.offset as usize;
+                for &prim in &self.indices[first..first + node.count as usize] {
+                    if let Some(t) = intersect(prim as usize, t_max) {
+                        found = true;
+                        if any_hit {
+                            return true;
+                        }
+                        t_max = t_max.min(t);
+                    }
+                }
+            } else {
+                // Push the far child first so the near one is visited next.
+                let left = node_idx + 1;
+                let right = node.offset;
+                let (near, far) = if ray.direction[node.axis as usize] < 0.0 {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                stack[stack_len] = far;
+                stack[stack_len + 1] = near;
+                stack_len += 2;
+            }
+        }
+        found
+    }
+}
+
+fn partition<T>(items: &mut [T], pred: impl Fn(&T) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+/// Scene shapes together with the hierarchy used to intersect them.
+///
+/// Unbounded shapes (planes) cannot be placed in the tree and are tested
+/// separately for every ray.
+pub struct Bvh {
+    shapes: Vec<Shape>,
+    tree: BvhTree,
+    bounded: Vec<usize>,
+    unbounded: Vec<usize>,
+    emitters: Vec<usize>,
+    /// Index of each shape's material among the distinct materials of the
+    /// scene, numbered in order of first use.
+    material_indices: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(shapes: Vec<Shape>) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut bounds = Vec::new();
+        for (i, shape) in shapes.iter().enumerate() {
+            match shape.bounding_box() {
+                Some(b) => {
+                    bounded.push(i);
+                    bounds.push(b);
+                }
+                None => unbounded.push(i),
+            }
+        }
+        let emitters = shapes
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.material().emission != Vec3::ZERO && s.is_sampleable())
+            .map(|(i, _)| i)
+            .collect();
+        let mut materials: Vec<Material> = Vec::new();
+        let material_indices = shapes
+            .iter()
+            .map(|s| {
+                let material = s.material();
+                materials
+                    .iter()
+                    .position(|m| m == material)
+                    .unwrap_or_else(|| {
+                        materials.push(*material);
+                        materials.len() - 1
+                    })
+            })
+            .collect();
+        Bvh {
+            tree: BvhTree::build(&bounds),
+            shapes,
+            bounded,
+            unbounded,
+            emitters,
+            material_indices,
+        }
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    /// Indices of the emissive shapes that can be sampled as light sources,
+    /// in ascending order.
+    pub fn emitters(&self) -> &[usize] {
+        &self.emitters
+    }
+
+    pub fn is_emitter(&self, shape_index: usize) -> bool {
+        self.emitters.binary_search(&shape_index).is_ok()
+    }
+
+    /// Index of the material of shape `shape_index`; shapes with equal
+    /// materials share the index.
+    pub fn material_index(&self, shape_index: usize) -> usize {
+        self.material_indices[shape_index]
+    }
+
+    /// Closest hit along the ray.
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        for &i in &self.unbounded {
+            if let Some(hit) = self.shapes[i].intersect(ray)
+                && best.as_ref().is_none_or(|b| hit.t < b.t)
+            {
+                best = Some(Hit {
+                    shape_index: i,
+                    ..hit
+                });
+            }
+        }
+        let t_max = best.as_ref().map_or(f32::INFINITY, |b| b.t);
+        self.tree.traverse(ray, t_max, false, |prim, t_max| {
+            let shape_index = self.bounded[prim];
+            let hit = self.shapes[shape_index].intersect(ray)?;
+            if hit.t < t_max {
+                let t = hit.t;
+                best = Some(Hit { shape_index, ..hit });
+                Some(t)
+            } else {
+                None
+            }
+        });
+        best
+    }
+
+    /// Whether any shape is hit at a distance in `(t_min, t_max)`, used for
+    /// shadow rays.
+    pub fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let blocks = |shape: &Shape| {
+            shape
+                .intersect(ray)
+                .is_some_and(|h| h.t > t_min && h.t < t_max)
+        };
+        self.unbounded.iter().any(|&i| blocks(&self.shapes[i]))
+            || self.tree.traverse(ray, t_max, true, |prim, _| {
+                blocks(&self.shapes[self.bounded[prim]]).then_some(0.0)
+            })
+    }
+}
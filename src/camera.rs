@@ -1,34 +1,158 @@
-use glam::{Quat, Vec3};
+use glam::{Quat, Vec2, Vec3};
+use std::f32::consts::{PI, TAU};
 
+use crate::sampling::{sample_concentric_disk, sample_regular_polygon};
 use crate::types::Ray;
 
+/// How image points map to rays.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// Pinhole perspective with the camera's field of view.
+    Perspective,
+    /// Parallel rays along the view direction. The image covers the area the
+    /// perspective view shows at the focus distance.
+    Orthographic,
+    /// Equidistant fisheye: the angle to the view direction grows linearly
+    /// with the distance from the image center, reaching `fov / 2` (radians)
+    /// on the inscribed circle. Points outside the circle see nothing.
+    Fisheye { fov: f32 },
+    /// Full 360° by 180° panorama in latitude/longitude layout.
+    Equirectangular,
+}
+
+/// Image axis along which [`Camera::fov`] is measured; the other axis follows
+/// from the aspect ratio.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FovAxis {
+    Horizontal,
+    #[default]
+    Vertical,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Camera {
+    /// Unit vectors spanning the image plane.
     pub up: Vec3,
     pub right: Vec3,
     pub look_dir: Vec3,
     pub pos: Vec3,
+    /// Field of view in degrees along `fov_axis`.
+    pub fov: f32,
+    pub fov_axis: FovAxis,
+    /// Width divided by height of the image.
+    pub aspect_ratio: f32,
+    /// Radius of the lens aperture; 0 gives a pinhole camera with everything
+    /// in focus.
+    pub aperture_radius: f32,
+    /// Distance along the view direction of the plane in focus.
+    pub focus_distance: f32,
+    /// Number of aperture blades shaping the lens as a regular polygon;
+    /// fewer than 3 give a round aperture.
+    pub aperture_blades: u32,
+    pub projection: Projection,
 }
 
 impl Camera {
+    /// Camera at `pos` looking at `look_at` with a vertical field of view of
+    /// `fov` degrees and a square image.
     pub fn new(pos: Vec3, look_at: Vec3, up: Vec3, fov: f32) -> Self {
         let look_dir = (look_at - pos).normalize();
-        let up = (up - look_dir.dot(up) * look_dir).normalize() * fov;
+        let up = (up - look_dir.dot(up) * look_dir).normalize();
         let right = look_dir.cross(up);
         Camera {
             up,
             right,
             look_dir,
             pos,
+            fov,
+            fov_axis: FovAxis::Vertical,
+            aspect_ratio: 1.0,
+            aperture_radius: 0.0,
+            focus_distance: (look_at - pos).length(),
+            aperture_blades: 0,
+            projection: Projection::Perspective,
+        }
+    }
+
+    /// Adapts the aspect ratio to an image of `width` by `height` pixels.
+    pub fn set_film_size(&mut self, width: u32, height: u32) {
+        self.aspect_ratio = width as f32 / height.max(1) as f32;
+    }
+
+    /// Width and height of the image plane at distance 1.
+    fn film_extent(&self) -> Vec2 {
+        let extent = 2.0 * (self.fov.to_radians() / 2.0).tan();
+        match self.fov_axis {
+            FovAxis::Horizontal => Vec2::new(extent, extent / self.aspect_ratio),
+            FovAxis::Vertical => Vec2::new(extent * self.aspect_ratio, extent),
         }
     }
-    pub fn generate_ray(&self, x: f32, y: f32) -> Ray {
-        let direction =
-            ((x - 0.5f32) * self.right + (1f32 - y - 0.5f32) * self.up + self.look_dir).normalize();
-        Ray {
-            origin: self.pos,
-            direction,
+
+    /// Ray through the image point (`x`, `y`) in `[0, 1]²`, starting from the
+    /// point of the lens selected by the uniform random numbers `lens`.
+    /// Returns `None` for points outside a fisheye's image circle.
+    pub fn generate_ray(&self, x: f32, y: f32, lens: Vec2) -> Option<Ray> {
+        // Film coordinates in [-0.5, 0.5]², y pointing up.
+        let film = Vec2::new(x - 0.5, 0.5 - y);
+        let (right, up) = (self.right, self.up);
+        let plane = film * self.film_extent();
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => (
+                self.pos,
+                (plane.x * right + plane.y * up + self.look_dir).normalize(),
+            ),
+            Projection::Orthographic => (
+                self.pos + self.focus_distance * (plane.x * right + plane.y * up),
+                self.look_dir,
+            ),
+            Projection::Fisheye { fov } => {
+                // Square pixels, with the image circle touching the shorter
+                // image edges.
+                let film = film
+                    * Vec2::new(
+                        self.aspect_ratio.max(1.0),
+                        self.aspect_ratio.recip().max(1.0),
+                    );
+                let r = film.length();
+                if r > 0.5 {
+                    return None;
+                }
+                let theta = r / 0.5 * fov / 2.0;
+                let radial = if r > 0.0 { film / r } else { Vec2::ZERO };
+                let direction =
+                    theta.sin() * (radial.x * right + radial.y * up) + theta.cos() * self.look_dir;
+                (self.pos, direction)
+            }
+            Projection::Equirectangular => {
+                let longitude = film.x * TAU;
+                let latitude = film.y * PI;
+                let direction = latitude.cos()
+                    * (longitude.sin() * right + longitude.cos() * self.look_dir)
+                    + latitude.sin() * up;
+                (self.pos, direction)
+            }
+        };
+
+        let has_lens = matches!(
+            self.projection,
+            Projection::Perspective | Projection::Orthographic
+        );
+        if self.aperture_radius <= 0.0 || !has_lens {
+            return Some(Ray { origin, direction });
         }
+
+        // All rays through the same image point meet on the focus plane.
+        let focus_point = origin + direction * (self.focus_distance / direction.dot(self.look_dir));
+        let lens_point = if self.aperture_blades >= 3 {
+            sample_regular_polygon(lens, self.aperture_blades)
+        } else {
+            sample_concentric_disk(lens)
+        } * self.aperture_radius;
+        let origin = origin + lens_point.x * right + lens_point.y * up;
+        Some(Ray {
+            origin,
+            direction: (focus_point - origin).normalize(),
+        })
     }
 
     pub fn move_along_up(&mut self, amount: f32) {
@@ -46,23 +170,13 @@ impl Camera {
         self.pos += dir * amount;
     }
 
-    fn fov(&self) -> f32 {
-        self.up.length()
-    }
-
     fn re_orthonormalize(&mut self) {
-        // Preserve fov magnitude encoded in up/right lengths
-        let fov = self.fov().max(1e-6);
         let l = self.look_dir.normalize();
         // Make up orthogonal to look
-        let mut u = (self.up - l * self.up.dot(l)).normalize();
-        let mut r = l.cross(u).normalize();
-        // Rescale up/right back to fov
-        u *= fov;
-        r *= fov;
+        let u = (self.up - l * self.up.dot(l)).normalize();
         self.look_dir = l;
         self.up = u;
-        self.right = r;
+        self.right = l.cross(u);
     }
 
     pub fn yaw(&mut self, angle: f32) {
@@ -0,0 +1,122 @@
+//! Denoising of path traced images.
+//!
+//! The edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) blurs the
+//! image with a 5×5 B3 spline kernel whose taps spread further apart on every
+//! iteration. Each tap is weighted by how similar the two pixels are in color
+//! and in the auxiliary features of their first hit (albedo, normal and
+//! depth), so that the blur stops at geometric and texture edges. The
+//! illumination is filtered with the albedo divided out, which keeps texture
+//! detail sharp.
+
+use glam::Vec3;
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use crate::aov::Features;
+use crate::tonemap::luminance;
+
+/// Options of [`denoise`]. The sigmas set how quickly the weight of a tap
+/// falls off with the difference of each quantity.
+#[derive(Copy, Clone, Debug)]
+pub struct DenoiseSettings {
+    /// Number of filter iterations; the kernel covers `4 * 2^iterations`
+    /// pixels.
+    pub iterations: u32,
+    /// Color difference, compared after compressing the dynamic range. It
+    /// halves on every iteration.
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_albedo: f32,
+    /// Depth difference relative to the depth of the filtered pixel.
+    pub sigma_depth: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 1.5,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+/// Albedo below which the illumination is not demodulated, so that black
+/// surfaces do not divide by zero.
+const MIN_ALBEDO: f32 = 1e-3;
+
+/// Denoises an image of average radiance per pixel guided by `features`,
+/// both of `width` by `height` pixels.
+pub fn denoise(
+    radiance: &[[f32; 3]],
+    features: &[Features],
+    width: u32,
+    height: u32,
+    settings: &DenoiseSettings,
+) -> Vec<[f32; 3]> {
+    let width = width as usize;
+    let height = height as usize;
+    let demodulation =
+        |f: &Features| Vec3::select(f.albedo.cmpgt(Vec3::splat(MIN_ALBEDO)), f.albedo, Vec3::ONE);
+
+    let mut current: Vec<Vec3> = radiance
+        .iter()
+        .zip(features)
+        .map(|(&c, f)| Vec3::from(c) / demodulation(f))
+        .collect();
+    let mut next = vec![Vec3::ZERO; current.len()];
+
+    const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+    // Compressed so that bright outliers do not dominate the color distance.
+    let compress = |c: Vec3| c / (1.0 + luminance(c).max(0.0));
+
+    for iteration in 0..settings.iterations {
+        let step = 1isize << iteration;
+        let sigma_color = settings.sigma_color / (1 << iteration) as f32;
+        let input = &current;
+        next.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let p = y * width + x;
+                let (color_p, features_p) = (compress(input[p]), &features[p]);
+                let mut sum = Vec3::ZERO;
+                let mut weight_sum = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = y as isize + (j as isize - 2) * step;
+                    if qy < 0 || qy >= height as isize {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x as isize + (i as isize - 2) * step;
+                        if qx < 0 || qx >= width as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let features_q = &features[q];
+                        let color = (color_p - compress(input[q])).length_squared()
+                            / (sigma_color * sigma_color);
+                        let normal = (features_p.normal - features_q.normal).length_squared()
+                            / (settings.sigma_normal * settings.sigma_normal);
+                        let albedo = (features_p.albedo - features_q.albedo).length_squared()
+                            / (settings.sigma_albedo * settings.sigma_albedo);
+                        let depth = (features_p.depth - features_q.depth).abs()
+                            / (settings.sigma_depth * features_p.depth.abs().max(1e-3));
+                        let weight = kx * ky * (-(color + normal + albedo + depth)).exp();
+                        sum += weight * input[q];
+                        weight_sum += weight;
+                    }
+                }
+                // The center tap always has a positive weight.
+                *out = sum / weight_sum;
+            }
+        });
+        std::mem::swap(&mut current, &mut next);
+    }
+
+    current
+        .iter()
+        .zip(features)
+        .map(|(&c, f)| (c * demodulation(f)).to_array())
+        .collect()
+}
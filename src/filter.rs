@@ -0,0 +1,153 @@
+//! Pixel reconstruction filters.
+//!
+//! Filters are applied by importance sampling: every camera sample is offset
+//! from the pixel center following the filter's shape, and the samples are
+//! then simply averaged. This keeps the frame buffer a plain running sum, so
+//! progressive accumulation works unchanged. Filters with negative lobes
+//! (Mitchell) are sampled proportionally to their absolute value and return
+//! negative weights for samples in the lobes.
+
+use glam::Vec2;
+
+/// Separable reconstruction filter, with radii measured in pixels.
+#[derive(Copy, Clone, Debug)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, sigma: f32 },
+    Mitchell { radius: f32, b: f32, c: f32 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    /// The filter with customary parameters for each kind, by name:
+    /// `box`, `tent`, `gaussian` or `mitchell`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "box" => Filter::Box { radius: 0.5 },
+            "tent" => Filter::Tent { radius: 1.0 },
+            "gaussian" => Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            "mitchell" => Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Box { .. } => "box",
+            Filter::Tent { .. } => "tent",
+            Filter::Gaussian { .. } => "gaussian",
+            Filter::Mitchell { .. } => "mitchell",
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    /// Unnormalized value of the filter along one axis, at offset `x` from
+    /// the pixel center.
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Filter::Box { radius } => (x <= radius) as u8 as f32,
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |d: f32| (-d * d / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                // The cubic is defined on [-2, 2].
+                let x = 2.0 * x / radius;
+                let value = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else if x < 2.0 {
+                    (-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                } else {
+                    0.0
+                };
+                value / 6.0
+            }
+        }
+    }
+}
+
+/// Tabulated distribution for importance sampling a [`Filter`].
+pub struct FilterSampler {
+    radius: f32,
+    /// Filter values at the bin centers.
+    values: Vec<f32>,
+    /// Running sum of the absolute bin values, normalized to end at 1.
+    cdf: Vec<f32>,
+    /// Ratio of the integrals of the absolute and the signed filter, which
+    /// makes the expected sample weight 1.
+    weight_scale: f32,
+}
+
+impl FilterSampler {
+    const BINS: usize = 64;
+
+    pub fn new(filter: &Filter) -> Self {
+        let radius = filter.radius();
+        let values: Vec<f32> = (0..Self::BINS)
+            .map(|i| {
+                let x = ((i as f32 + 0.5) / Self::BINS as f32 * 2.0 - 1.0) * radius;
+                filter.evaluate(x)
+            })
+            .collect();
+        let abs_sum: f32 = values.iter().map(|v| v.abs()).sum();
+        let signed_sum: f32 = values.iter().sum();
+        let cdf = values
+            .iter()
+            .scan(0.0, |total, v| {
+                *total += v.abs() / abs_sum;
+                Some(*total)
+            })
+            .collect();
+        FilterSampler {
+            radius,
+            values,
+            cdf,
+            weight_scale: abs_sum / signed_sum,
+        }
+    }
+
+    /// Offset from the pixel center in pixels for the uniform random
+    /// numbers `u`, and the weight of the sample.
+    pub fn sample(&self, u: Vec2) -> (Vec2, f32) {
+        let (x, wx) = self.sample_1d(u.x);
+        let (y, wy) = self.sample_1d(u.y);
+        (Vec2::new(x, y), wx * wy)
+    }
+
+    fn sample_1d(&self, u: f32) -> (f32, f32) {
+        let bin = self.cdf.partition_point(|&c| c <= u).min(Self::BINS - 1);
+        let start = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let within = ((u - start) / (self.cdf[bin] - start)).clamp(0.0, 1.0);
+        let x = ((bin as f32 + within) / Self::BINS as f32 * 2.0 - 1.0) * self.radius;
+        let weight = self.weight_scale.copysign(self.values[bin]);
+        (x, weight)
+    }
+}
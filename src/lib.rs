@@ -0,0 +1,29 @@
+//! Core of the rustcast ray tracer: scene description, intersection and
+//! rendering into a linear floating point frame buffer.
+//!
+//! The interactive viewer lives in the `rustcast` binary behind the `viewer`
+//! feature; `rustcast-render` renders headless to image files.
+
+pub mod adaptive;
+pub mod aov;
+pub mod bsdf;
+pub mod bvh;
+pub mod camera;
+pub mod denoise;
+pub mod filter;
+pub mod obj;
+pub mod output;
+pub mod renderer;
+pub mod sampler;
+pub mod sampling;
+pub mod scene_file;
+pub mod scenes;
+pub mod shape;
+pub mod tonemap;
+pub mod types;
+
+pub use bvh::Bvh;
+pub use camera::Camera;
+pub use renderer::{RenderMode, RenderSettings, draw_adaptive_frame, draw_frame};
+pub use shape::Shape;
+pub use types::{Hit, Light, Material, Ray, Transform};
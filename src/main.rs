@@ -1,25 +1,118 @@
-mod camera;
-mod renderer;
-mod scenes;
-mod shape;
-mod types;
-
 use pixels::{Pixels, SurfaceTexture};
 use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
 use winit::event_loop::EventLoop;
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::WindowBuilder;
 
-use crate::camera::Camera;
-use crate::renderer::{RenderMode, draw_frame};
-use crate::scenes::{
-    make_axes_scene, make_cornell_scene, make_default_scene, make_scene_cylinder_plane,
-};
-use crate::shape::Shape;
-use crate::types::Light;
-use std::time::Instant;
+use rustcast::adaptive::{AdaptiveBuffer, AdaptiveSettings};
+use rustcast::aov::Aov;
+use rustcast::bvh::Bvh;
+use rustcast::camera::Projection;
+use rustcast::denoise::{DenoiseSettings, denoise};
+use rustcast::filter::Filter;
+use rustcast::output::{BitDepth, ImageFormat, ImageMetadata, to_8bit, write_exr, write_image};
+use rustcast::renderer::{RenderMode, RenderSettings, draw_adaptive_frame};
+use rustcast::sampler::SamplerKind;
+use rustcast::scene_file::load_scene_file;
+use rustcast::scenes::load_scene;
+use rustcast::tonemap::{ToneMapOperator, ToneMapSettings, tone_map};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Fraction of the render resolution used while a movement key is held, so
+/// the preview keeps up with the camera.
+const MOVING_SCALE: f32 = 0.5;
+
+/// Noise threshold of adaptive sampling when V turns it on.
+const NOISE_THRESHOLD: f32 = 0.05;
+
+/// Samples accumulated at the render resolution, which may differ from the
+/// window size.
+struct Accumulation {
+    buffer: AdaptiveBuffer,
+    render_time: Duration,
+    /// Denoised image of the samples so far, cleared when they change.
+    denoised: Option<Vec<[f32; 3]>>,
+}
+
+impl Accumulation {
+    fn new(width: u32, height: u32) -> Self {
+        Accumulation {
+            buffer: AdaptiveBuffer::new(width, height),
+            render_time: Duration::ZERO,
+            denoised: None,
+        }
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.buffer.width, self.buffer.height)
+    }
+
+    fn reset(&mut self) {
+        self.buffer.reset();
+        self.render_time = Duration::ZERO;
+        self.denoised = None;
+    }
+
+    /// Average radiance per pixel, denoised if `denoising`. The denoiser only
+    /// runs again after new samples were added.
+    fn radiance(&mut self, denoising: bool) -> Vec<[f32; 3]> {
+        if !denoising {
+            return self.buffer.radiance();
+        }
+        let (width, height) = self.size();
+        let buffer = &self.buffer;
+        self.denoised
+            .get_or_insert_with(|| {
+                denoise(
+                    &buffer.radiance(),
+                    &buffer.features(),
+                    width,
+                    height,
+                    &DenoiseSettings::default(),
+                )
+            })
+            .clone()
+    }
+}
+
+/// Render resolution for a window of `width` by `height` pixels rendered at
+/// `scale` of its size.
+fn render_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scaled = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Nearest neighbor upscaling of the RGB `image` of `width` by `height`
+/// pixels into the RGBA `frame` of `frame_width` by `frame_height` pixels.
+fn upscale(
+    image: &[[u8; 3]],
+    (width, height): (u32, u32),
+    frame: &mut [u8],
+    (frame_width, frame_height): (u32, u32),
+) {
+    for (y, row) in frame.chunks_exact_mut(4 * frame_width as usize).enumerate() {
+        let src_y = (y as u64 * height as u64 / frame_height as u64) as usize;
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let src_x = (x as u64 * width as u64 / frame_width as u64) as usize;
+            let [r, g, b] = image[src_y * width as usize + src_x];
+            px.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Optional scene file from the command line; Z still cycles the built-in scenes
+    let scene_path = std::env::args().nth(1);
+    // Headless rendering used to be the `render` subcommand of the viewer
+    if scene_path.as_deref() == Some("render") {
+        eprintln!(
+            "headless rendering moved to its own binary; \
+             run `rustcast-render` with the same options instead"
+        );
+        std::process::exit(1);
+    }
     // Create event loop and window
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
@@ -35,28 +128,35 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
     let mut pixels = Pixels::new(size.width, size.height, surface_texture)?;
 
-    // Track framebuffer size for drawing
-    let mut fb_width = size.width;
-    let mut fb_height = size.height;
+    // The pixel buffer follows the window size; the scene is rendered at
+    // `render_scale` of it (G cycles 100%, 50% and 25%) and upscaled.
+    let mut window_size = (size.width, size.height);
+    let mut render_scale = 1.0f32;
+    // Movement keys currently held; the render scale drops while any is held
+    let mut held_movement_keys = HashSet::new();
 
-    let mut render_mode = RenderMode::Raycast;
+    let mut settings = RenderSettings::default();
+    // Adaptive sampling is off until V sets a noise threshold
+    let mut adaptive = AdaptiveSettings::default();
+    let mut tone_mapping = ToneMapSettings::default();
+    let mut denoising = false;
     let mut scene: u8 = 3;
 
     // Current scene data (camera/light/shapes)
-    fn load_scene(scene: u8) -> (Camera, Vec<Light>, Vec<Shape>) {
-        match scene {
-            1 => make_cornell_scene(),
-            2 => make_axes_scene(),
-            3 => make_scene_cylinder_plane(),
-            _ => make_default_scene(),
-        }
-    }
-    let (mut camera, mut lights, mut shapes) = load_scene(scene);
+    let (mut camera, mut lights, shapes) = match &scene_path {
+        Some(path) => load_scene_file(Path::new(path))?,
+        None => load_scene(scene),
+    };
+    let (width, height) = render_size(window_size.0, window_size.1, render_scale);
+    camera.set_film_size(width, height);
+    let mut bvh = Bvh::new(shapes);
+    let mut scene_name = scene_path
+        .clone()
+        .unwrap_or_else(|| format!("builtin scene {}", scene));
 
     let mut shift_down = false;
 
-    let mut frame_buffer = vec![0.0; 3 * 1024 * 1024];
-    let mut sample_count = 0;
+    let mut accumulation = Accumulation::new(width, height);
 
     // Run the event loop
     Ok(event_loop.run(move |event, elwt| {
@@ -66,65 +166,71 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     elwt.exit();
                 }
                 WindowEvent::Resized(new_size) => {
-                    // Resize the surface to match the new window size
-                    fb_width = new_size.width;
-                    fb_height = new_size.height;
+                    // Minimized windows report a zero size; keep the buffers
+                    if new_size.width == 0 || new_size.height == 0 {
+                        return;
+                    }
+                    // Resize the surface and the pixel buffer to the new window
+                    // size; the render resolution follows at the next redraw
+                    window_size = (new_size.width, new_size.height);
                     let _ = pixels.resize_surface(new_size.width, new_size.height);
+                    let _ = pixels.resize_buffer(new_size.width, new_size.height);
+                    window.request_redraw();
+                }
+                WindowEvent::Focused(false) => {
+                    // Keys released while unfocused send no event
+                    held_movement_keys.clear();
+                    window.request_redraw();
                 }
                 WindowEvent::ScaleFactorChanged { .. } => {
                     // Request redraw to adapt to scaling changes
                     window.request_redraw();
                 }
                 WindowEvent::RedrawRequested => {
-                    // Draw our scene into the frame and measure draw time
+                    // Reallocate the accumulation when the render resolution
+                    // changed, which restarts it
+                    let scale = if !held_movement_keys.is_empty() {
+                        render_scale * MOVING_SCALE
+                    } else {
+                        render_scale
+                    };
+                    let (width, height) = render_size(window_size.0, window_size.1, scale);
+                    if (width, height) != accumulation.size() {
+                        accumulation = Accumulation::new(width, height);
+                        camera.set_film_size(width, height);
+                    }
+
+                    // Draw our scene into the frame and measure draw time;
+                    // nothing is left to draw once every pixel converged
                     let start = Instant::now();
-                    sample_count += draw_frame(
-                        &mut frame_buffer,
-                        fb_width,
-                        fb_height,
-                        render_mode,
+                    let sampled = draw_adaptive_frame(
+                        &mut accumulation.buffer,
+                        &settings,
+                        &adaptive,
                         &camera,
                         &lights,
-                        &shapes,
+                        &bvh,
                     );
-                    let elapsed = start.elapsed();
-                    println!("Draw time: {:9.3} ms. Sample {:5} computed", elapsed.as_secs_f64() * 1000.0, sample_count);
-
-                    let inv_gamma = 1.0 / 1.8;
-                    let max = frame_buffer
-                        .clone()
-                        .into_iter()
-                        .reduce(f32::max)
-                        .unwrap_or(0.0);
-                    for y in 0..fb_height {
-                        for x in 0..fb_width {
-                            let idx_px = (y * fb_width + x) as usize;
-                            let idx_fb = idx_px * 3;
-
-                            // average radiance ----------------------------------------------------
-                            let mut r = frame_buffer[idx_fb] / sample_count as f32;
-                            let mut g = frame_buffer[idx_fb + 1] / sample_count as f32;
-                            let mut b = frame_buffer[idx_fb + 2] / sample_count as f32;
-
-                            // simple tone map: c' = c / (1 + c)  (Reinhard) -----------------------
-                            let half_intensity = 0.8 * max / sample_count as f32;
-                            r = r / (half_intensity + r);
-                            g = g / (half_intensity + g);
-                            b = b / (half_intensity + b);
-
-                            // γ-correction ---------------------------------------------------------
-                            r = r.powf(inv_gamma);
-                            g = g.powf(inv_gamma);
-                            b = b.powf(inv_gamma);
-
-                            let idx_img = idx_px * 4;
-                            pixels.frame_mut()[idx_img] = (r * 255.0) as u8;
-                            pixels.frame_mut()[idx_img + 1] = (g * 255.0) as u8;
-                            pixels.frame_mut()[idx_img + 2] = (b * 255.0) as u8;
-                            pixels.frame_mut()[idx_img + 3] = 255;
-                        }
+                    if sampled > 0 {
+                        let elapsed = start.elapsed();
+                        accumulation.render_time += elapsed;
+                        accumulation.denoised = None;
+                        println!(
+                            "Draw time: {:9.3} ms. {:7} pixels sampled, {:7.1} samples per pixel at {}x{}",
+                            elapsed.as_secs_f64() * 1000.0,
+                            sampled,
+                            accumulation.buffer.mean_samples(),
+                            width,
+                            height
+                        );
                     }
 
+                    let radiance = accumulation.radiance(denoising);
+                    let rgb = to_8bit(&tone_map(&radiance, &tone_mapping));
+                    let texture = pixels.texture();
+                    let frame_size = (texture.width(), texture.height());
+                    upscale(&rgb, (width, height), pixels.frame_mut(), frame_size);
+
                     // Render to the window
                     if pixels.render().is_err() {
                         // If rendering fails, exit the app
@@ -150,15 +256,145 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         _ => {}
                     }
 
-                    // Handle non-repeating key presses for discrete steps
-                    if state == ElementState::Pressed && !repeat {
-                        frame_buffer.fill(0.0);
-                        sample_count = 0;
+                    let pressed = state == ElementState::Pressed && !repeat;
+                    if let PhysicalKey::Code(
+                        code @ (KeyCode::KeyW
+                        | KeyCode::KeyA
+                        | KeyCode::KeyS
+                        | KeyCode::KeyD
+                        | KeyCode::KeyR
+                        | KeyCode::ArrowLeft
+                        | KeyCode::ArrowRight
+                        | KeyCode::ArrowUp
+                        | KeyCode::ArrowDown),
+                    ) = physical_key
+                    {
+                        if state == ElementState::Pressed {
+                            held_movement_keys.insert(code);
+                        } else if held_movement_keys.remove(&code)
+                            && held_movement_keys.is_empty()
+                        {
+                            // Back to the full render resolution
+                            window.request_redraw();
+                        }
+                    }
+
+                    if pressed && physical_key == PhysicalKey::Code(KeyCode::KeyO) {
+                        // Save the render so far as shown, keeping the
+                        // accumulation
+                        let (width, height) = accumulation.size();
+                        let radiance = accumulation.radiance(denoising);
+                        let metadata = ImageMetadata {
+                            scene: scene_name.clone(),
+                            samples: accumulation.buffer.mean_samples().round() as u32,
+                            render_time: accumulation.render_time,
+                        };
+                        // The EXR also gets every output variable as a layer
+                        let features = accumulation.buffer.features();
+                        let results = [
+                            (
+                                "render.png",
+                                write_image(
+                                    Path::new("render.png"),
+                                    ImageFormat::Png,
+                                    BitDepth::Eight,
+                                    width,
+                                    height,
+                                    &radiance,
+                                    &tone_mapping,
+                                    &metadata,
+                                ),
+                            ),
+                            (
+                                "render.exr",
+                                write_exr(
+                                    Path::new("render.exr"),
+                                    width,
+                                    height,
+                                    &radiance,
+                                    &features,
+                                    &Aov::ALL,
+                                    &metadata,
+                                ),
+                            ),
+                        ];
+                        for (path, result) in results {
+                            match result {
+                                Ok(()) => println!("Wrote {}", path),
+                                Err(e) => eprintln!("Cannot write {}: {}", path, e),
+                            }
+                        }
+                    } else if pressed
+                        && let PhysicalKey::Code(
+                            code @ (KeyCode::KeyT
+                            | KeyCode::KeyX
+                            | KeyCode::Comma
+                            | KeyCode::Period),
+                        ) = physical_key
+                    {
+                        // Tone mapping: T cycles operators, X toggles auto
+                        // exposure, comma and period change the exposure.
+                        // The accumulated samples stay valid.
+                        match code {
+                            KeyCode::KeyT => {
+                                let next = match tone_mapping.operator {
+                                    ToneMapOperator::Linear => "reinhard",
+                                    ToneMapOperator::Reinhard => "extended-reinhard",
+                                    ToneMapOperator::ExtendedReinhard { .. } => "aces",
+                                    ToneMapOperator::Aces => "hable",
+                                    ToneMapOperator::Hable => "linear",
+                                };
+                                tone_mapping.operator = ToneMapOperator::from_name(next).unwrap();
+                            }
+                            KeyCode::KeyX => tone_mapping.auto_exposure ^= true,
+                            KeyCode::Comma => tone_mapping.exposure -= 0.5,
+                            _ => tone_mapping.exposure += 0.5,
+                        }
+                        println!(
+                            "Tone mapping: {}, exposure {:+.1} EV{}",
+                            tone_mapping.operator.name(),
+                            tone_mapping.exposure,
+                            if tone_mapping.auto_exposure {
+                                " (auto)"
+                            } else {
+                                ""
+                            }
+                        );
+                        window.request_redraw();
+                    } else if pressed && physical_key == PhysicalKey::Code(KeyCode::KeyE) {
+                        // Toggle the denoiser, which only post-processes the
+                        // accumulated samples
+                        denoising ^= true;
+                        println!("Denoising: {}", if denoising { "on" } else { "off" });
+                        window.request_redraw();
+                    } else if pressed && physical_key == PhysicalKey::Code(KeyCode::KeyV) {
+                        // Toggle adaptive sampling; converged pixels simply
+                        // resume sampling when it is turned off
+                        adaptive.noise_threshold = if adaptive.noise_threshold > 0.0 {
+                            0.0
+                        } else {
+                            NOISE_THRESHOLD
+                        };
+                        println!("Adaptive sampling noise threshold: {}", adaptive.noise_threshold);
+                        window.request_redraw();
+                    } else if pressed && physical_key == PhysicalKey::Code(KeyCode::KeyG) {
+                        // Cycle the render scale; the accumulation restarts at
+                        // the new resolution on the next redraw
+                        render_scale = match render_scale {
+                            s if s > 0.5 => 0.5,
+                            s if s > 0.25 => 0.25,
+                            _ => 1.0,
+                        };
+                        println!("Render scale: {:.0}%", render_scale * 100.0);
+                        window.request_redraw();
+                    } else if pressed {
+                        // Handle non-repeating key presses for discrete steps
+                        accumulation.reset();
                         let move_step = 0.2f32;
                         let rot_step = 0.05f32; // radians
                         match physical_key {
                             PhysicalKey::Code(KeyCode::KeyN) => {
-                                render_mode = match render_mode {
+                                settings.render_mode = match settings.render_mode {
                                     RenderMode::Raycast => RenderMode::Raytrace,
                                     RenderMode::Raytrace => RenderMode::Normals,
                                     RenderMode::Normals => RenderMode::Pathtracing,
@@ -166,13 +402,84 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 };
                                 window.request_redraw();
                             }
+                            PhysicalKey::Code(KeyCode::KeyF) => {
+                                // Cycle pixel reconstruction filters
+                                let next = match settings.filter {
+                                    Filter::Box { .. } => "tent",
+                                    Filter::Tent { .. } => "gaussian",
+                                    Filter::Gaussian { .. } => "mitchell",
+                                    Filter::Mitchell { .. } => "box",
+                                };
+                                settings.filter = Filter::from_name(next).unwrap();
+                                println!("Filter: {}", settings.filter.name());
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::KeyM) => {
+                                // Cycle random number samplers
+                                settings.sampler = match settings.sampler {
+                                    SamplerKind::Independent => SamplerKind::Stratified,
+                                    SamplerKind::Stratified => SamplerKind::Halton,
+                                    SamplerKind::Halton => SamplerKind::Sobol,
+                                    SamplerKind::Sobol => SamplerKind::Independent,
+                                };
+                                println!("Sampler: {}", settings.sampler.name());
+                                window.request_redraw();
+                            }
+                            // Depth of field: [ and ] change the aperture,
+                            // - and = the focus distance, B the blade count
+                            PhysicalKey::Code(KeyCode::BracketLeft) => {
+                                camera.aperture_radius = (camera.aperture_radius - 0.01).max(0.0);
+                                println!("Aperture radius: {:.2}", camera.aperture_radius);
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::BracketRight) => {
+                                camera.aperture_radius += 0.01;
+                                println!("Aperture radius: {:.2}", camera.aperture_radius);
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::Minus) => {
+                                camera.focus_distance /= 1.1;
+                                println!("Focus distance: {:.2}", camera.focus_distance);
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::Equal) => {
+                                camera.focus_distance *= 1.1;
+                                println!("Focus distance: {:.2}", camera.focus_distance);
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::KeyB) => {
+                                camera.aperture_blades = match camera.aperture_blades {
+                                    0..=4 => 5,
+                                    5 => 6,
+                                    6 => 8,
+                                    _ => 0,
+                                };
+                                println!("Aperture blades: {}", camera.aperture_blades);
+                                window.request_redraw();
+                            }
+                            PhysicalKey::Code(KeyCode::KeyP) => {
+                                // Cycle camera projections
+                                camera.projection = match camera.projection {
+                                    Projection::Perspective => Projection::Orthographic,
+                                    Projection::Orthographic => Projection::Fisheye {
+                                        fov: 180f32.to_radians(),
+                                    },
+                                    Projection::Fisheye { .. } => Projection::Equirectangular,
+                                    Projection::Equirectangular => Projection::Perspective,
+                                };
+                                println!("Projection: {:?}", camera.projection);
+                                window.request_redraw();
+                            }
                             PhysicalKey::Code(KeyCode::KeyZ) => {
                                 // Cycle scenes: 0 (default), 1 (cornell), 2 (axes), 3 (cylinder+plane)
                                 scene = (scene + 1) % 4;
                                 let (c, l, s) = load_scene(scene);
+                                scene_name = format!("builtin scene {}", scene);
                                 camera = c;
+                                let (width, height) = accumulation.size();
+                                camera.set_film_size(width, height);
                                 lights = l;
-                                shapes = s;
+                                bvh = Bvh::new(s);
                                 window.request_redraw();
                             }
                             // Movement keys
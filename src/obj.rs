@@ -0,0 +1,358 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Supported OBJ statements are `v`, `vn`, `vt`, `f` (triangles and convex
+//! polygons, which are fan triangulated, with `v`, `v/vt`, `v//vn` and
+//! `v/vt/vn` vertices and negative relative indices), `g`/`o` groups,
+//! `mtllib` and `usemtl`. Texture coordinates are validated but not used.
+//! Every group/material combination becomes one [`Shape::Mesh`].
+//!
+//! MTL colors map onto [`Material`] as follows: `Kd` is the `color`, `Ke`
+//! the `emission`, the average of `Ka` the `ambient` factor, the average of
+//! `Ks` the `specular_coef`, `Ns` the `shininess`, `Ni` the `ior` and the
+//! transparency `Tr` (or `1 - d`) the `transmission`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::Vec3;
+
+use crate::scene_file::ParseError;
+use crate::shape::{Mesh, Shape};
+use crate::types::Material;
+
+/// Loads the OBJ file at `path` together with the MTL libraries it
+/// references, resolved relative to the OBJ file. Faces without a known
+/// material use `default_material`.
+pub fn load_obj(
+    path: &Path,
+    default_material: Material,
+) -> Result<Vec<Shape>, Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut materials = HashMap::new();
+    for (line, library) in mtl_libraries(&source) {
+        let mtl_path = dir.join(library);
+        let mtl = std::fs::read_to_string(&mtl_path).map_err(|e| {
+            format!(
+                "{}:{}: cannot read {}: {}",
+                path.display(),
+                line,
+                mtl_path.display(),
+                e
+            )
+        })?;
+        let parsed = parse_mtl(&mtl, default_material)
+            .map_err(|e| format!("{}:{}", mtl_path.display(), e))?;
+        materials.extend(parsed);
+    }
+
+    parse_obj(&source, &materials, default_material)
+        .map_err(|e| format!("{}:{}", path.display(), e).into())
+}
+
+/// Line numbers and names of the `mtllib` statements in an OBJ source.
+fn mtl_libraries(source: &str) -> Vec<(usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let mut words = line.split_whitespace();
+            (words.next() == Some("mtllib")).then(|| words.map(move |w| (i + 1, w)))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Parses an MTL library. Unspecified properties keep the values of `base`.
+pub fn parse_mtl(source: &str, base: Material) -> Result<HashMap<String, Material>, ParseError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut words = Words::new(line, line_idx + 1);
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        if keyword.text == "newmtl" {
+            let name = words.expect("material name")?;
+            materials.extend(current.take());
+            current = Some((name.text.to_string(), base));
+            continue;
+        }
+        let Some((_, material)) = current.as_mut() else {
+            return Err(keyword.error("statement before 'newmtl'"));
+        };
+        match keyword.text {
+            "Kd" => material.color = words.vector()?,
+            "Ka" => material.ambient = average(words.vector()?),
+            "Ke" => material.emission = words.vector()?,
+            "Ks" => material.specular_coef = average(words.vector()?),
+            "Ns" => material.shininess = words.number()?,
+            "Ni" => material.ior = words.number()?,
+            "Tr" => material.transmission = words.number()?,
+            "d" => material.transmission = 1.0 - words.number()?,
+            // Other statements (textures, illumination models, ...) are ignored.
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    Ok(materials)
+}
+
+fn average(v: Vec3) -> f32 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+/// Parses an OBJ source into one mesh per group and material.
+pub fn parse_obj(
+    source: &str,
+    materials: &HashMap<String, Material>,
+    default_material: Material,
+) -> Result<Vec<Shape>, ParseError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut texcoord_count = 0;
+
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut group = String::new();
+    let mut material_name = String::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut words = Words::new(line, line_idx + 1);
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        match keyword.text {
+            "v" => positions.push(words.vector()?),
+            "vn" => normals.push(words.vector()?),
+            "vt" => {
+                words.number()?;
+                texcoord_count += 1;
+            }
+            "g" | "o" => {
+                group = words.rest().to_string();
+                current = None;
+            }
+            "usemtl" => {
+                material_name = words.expect("material name")?.text.to_string();
+                current = None;
+            }
+            "f" => {
+                let mut face = Vec::new();
+                for word in words.by_ref() {
+                    face.push(parse_face_vertex(
+                        word,
+                        positions.len(),
+                        texcoord_count,
+                        normals.len(),
+                    )?);
+                }
+                if face.len() < 3 {
+                    return Err(keyword.error("face needs at least 3 vertices"));
+                }
+                let builder = *current.get_or_insert_with(|| {
+                    builders
+                        .iter()
+                        .position(|b| b.group == group && b.material == material_name)
+                        .unwrap_or_else(|| {
+                            builders.push(MeshBuilder::new(&group, &material_name));
+                            builders.len() - 1
+                        })
+                });
+                builders[builder].add_face(&face, &positions, &normals);
+            }
+            // Other statements (smoothing groups, lines, ...) are ignored.
+            _ => {}
+        }
+    }
+
+    Ok(builders
+        .into_iter()
+        .filter(|b| !b.indices.is_empty())
+        .map(|b| {
+            let material = materials
+                .get(&b.material)
+                .copied()
+                .unwrap_or(default_material);
+            let normals = b.has_normals.then_some(b.normals);
+            Shape::Mesh {
+                mesh: Arc::new(Mesh::new(b.positions, b.indices, normals)),
+                material,
+            }
+        })
+        .collect())
+}
+
+/// Position and optional normal index of a face vertex.
+type FaceVertex = (usize, Option<usize>);
+
+fn parse_face_vertex(
+    word: Word<'_>,
+    position_count: usize,
+    texcoord_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, ParseError> {
+    let resolve = |part: &str, count: usize, what: &str| -> Result<usize, ParseError> {
+        let index: i64 = part
+            .parse()
+            .map_err(|_| word.error(format!("invalid face vertex '{}'", word.text)))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(word.error(format!("{} index {} out of range", what, index)));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut parts = word.text.split('/');
+    let position = resolve(parts.next().unwrap_or(""), position_count, "vertex")?;
+    if let Some(texcoord) = parts.next().filter(|p| !p.is_empty()) {
+        resolve(texcoord, texcoord_count, "texture coordinate")?;
+    }
+    let normal = match parts.next() {
+        Some(normal) => Some(resolve(normal, normal_count, "normal")?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return Err(word.error(format!("invalid face vertex '{}'", word.text)));
+    }
+    Ok((position, normal))
+}
+
+/// Collects the triangles of one group/material combination, re-indexing the
+/// OBJ position/normal pairs into a single vertex buffer.
+struct MeshBuilder {
+    group: String,
+    material: String,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    indices: Vec<[u32; 3]>,
+    vertex_map: HashMap<FaceVertex, u32>,
+    has_normals: bool,
+}
+
+impl MeshBuilder {
+    fn new(group: &str, material: &str) -> Self {
+        MeshBuilder {
+            group: group.to_string(),
+            material: material.to_string(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            vertex_map: HashMap::new(),
+            has_normals: true,
+        }
+    }
+
+    fn add_face(&mut self, face: &[FaceVertex], positions: &[Vec3], normals: &[Vec3]) {
+        let indices: Vec<u32> = face
+            .iter()
+            .map(|&(position, normal)| {
+                self.has_normals &= normal.is_some();
+                *self
+                    .vertex_map
+                    .entry((position, normal))
+                    .or_insert_with(|| {
+                        self.positions.push(positions[position]);
+                        self.normals
+                            .push(normal.map_or(Vec3::ZERO, |n| normals[n].normalize()));
+                        self.positions.len() as u32 - 1
+                    })
+            })
+            .collect();
+        // Fan triangulation, exact for convex polygons.
+        for i in 1..indices.len() - 1 {
+            self.indices.push([indices[0], indices[i], indices[i + 1]]);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Word<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Word<'_> {
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Whitespace separated words of a line, ignoring `#` comments.
+struct Words<'a> {
+    line: &'a str,
+    line_number: usize,
+    pos: usize,
+}
+
+impl<'a> Words<'a> {
+    fn new(line: &'a str, line_number: usize) -> Self {
+        Words {
+            line: line.split('#').next().unwrap_or(""),
+            line_number,
+            pos: 0,
+        }
+    }
+
+    fn end_error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line_number,
+            column: self.line.chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn expect(&mut self, what: &str) -> Result<Word<'a>, ParseError> {
+        self.next()
+            .ok_or_else(|| self.end_error(format!("expected {}", what)))
+    }
+
+    fn number(&mut self) -> Result<f32, ParseError> {
+        let word = self.expect("a number")?;
+        word.text
+            .parse()
+            .map_err(|_| word.error(format!("expected a number, found '{}'", word.text)))
+    }
+
+    fn vector(&mut self) -> Result<Vec3, ParseError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// The remainder of the line with surrounding whitespace removed.
+    fn rest(&mut self) -> &'a str {
+        let rest = self.line[self.pos..].trim();
+        self.pos = self.line.len();
+        rest
+    }
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = Word<'a>;
+
+    fn next(&mut self) -> Option<Word<'a>> {
+        let rest = &self.line[self.pos..];
+        let start = self.pos + rest.find(|c: char| !c.is_whitespace())?;
+        let end = self.line[start..]
+            .find(char::is_whitespace)
+            .map_or(self.line.len(), |i| start + i);
+        self.pos = end;
+        Some(Word {
+            text: &self.line[start..end],
+            line: self.line_number,
+            column: self.line[..start].chars().count() + 1,
+        })
+    }
+}
@@ -0,0 +1,309 @@
+//! Writing renders to image files.
+//!
+//! High dynamic range formats (PFM and OpenEXR) store the average linear
+//! radiance per pixel as 32-bit floats; PNG and PPM store the image tone
+//! mapped by [`crate::tonemap`] with 8 or 16 bits per channel. Render
+//! metadata is embedded as PNG text chunks, PPM header comments and EXR
+//! header attributes; PFM has no place for it. OpenEXR files can also hold
+//! the output variables of [`crate::aov`] as extra layers.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::aov::{Aov, Features};
+use crate::tonemap::{ToneMapSettings, tone_map};
+
+/// Average radiance per pixel of a frame buffer holding the sum of
+/// `sample_count` samples.
+pub fn average_radiance(
+    frame_buffer: &[f32],
+    pixel_count: usize,
+    sample_count: u32,
+) -> Vec<[f32; 3]> {
+    let inv_samples = 1.0 / sample_count.max(1) as f32;
+    frame_buffer[..pixel_count * 3]
+        .as_chunks::<3>()
+        .0
+        .iter()
+        .map(|px| px.map(|c| c * inv_samples))
+        .collect()
+}
+
+/// Quantizes display values in `[0, 1]` to 8 bits.
+pub fn to_8bit(rgb: &[[f32; 3]]) -> Vec<[u8; 3]> {
+    rgb.iter()
+        .map(|px| px.map(|c| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8))
+        .collect()
+}
+
+/// Quantizes display values in `[0, 1]` to 16 bits.
+pub fn to_16bit(rgb: &[[f32; 3]]) -> Vec<[u16; 3]> {
+    rgb.iter()
+        .map(|px| px.map(|c| (c.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16))
+        .collect()
+}
+
+/// Information about a render, embedded in the image where the format allows.
+#[derive(Clone, Debug, Default)]
+pub struct ImageMetadata {
+    pub scene: String,
+    pub samples: u32,
+    pub render_time: Duration,
+}
+
+impl ImageMetadata {
+    fn entries(&self) -> [(&'static str, String); 4] {
+        [
+            ("Software", "rustcast".to_string()),
+            ("Scene", self.scene.clone()),
+            ("Samples", self.samples.to_string()),
+            (
+                "RenderTime",
+                format!("{:.3} s", self.render_time.as_secs_f64()),
+            ),
+        ]
+    }
+}
+
+/// Image file formats supported for headless output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+    /// Portable float map, linear float32.
+    Pfm,
+    /// OpenEXR, linear float32.
+    Exr,
+}
+
+/// Bits per channel of the low dynamic range formats.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl ImageFormat {
+    /// Picks the format from the file extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format stores linear radiance rather than tone mapped
+    /// display values.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, ImageFormat::Pfm | ImageFormat::Exr)
+    }
+}
+
+/// Writes the average radiance per pixel, tone mapped for the low dynamic
+/// range formats.
+#[allow(clippy::too_many_arguments)]
+pub fn write_image(
+    path: &Path,
+    format: ImageFormat,
+    depth: BitDepth,
+    width: u32,
+    height: u32,
+    radiance: &[[f32; 3]],
+    tone_mapping: &ToneMapSettings,
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if format.is_hdr() {
+        return match format {
+            ImageFormat::Exr => write_exr(path, width, height, radiance, &[], &[], metadata),
+            _ => write_pfm(path, width, height, radiance),
+        };
+    }
+
+    let rgb = tone_map(radiance, tone_mapping);
+    match (format, depth) {
+        (ImageFormat::Png, BitDepth::Eight) => {
+            write_png(path, width, height, &to_8bit(&rgb), metadata)
+        }
+        (ImageFormat::Png, BitDepth::Sixteen) => {
+            write_png16(path, width, height, &to_16bit(&rgb), metadata)
+        }
+        (_, BitDepth::Eight) => write_ppm(path, width, height, &to_8bit(&rgb), metadata),
+        (_, BitDepth::Sixteen) => write_ppm16(path, width, height, &to_16bit(&rgb), metadata),
+    }
+}
+
+fn png_encoder(
+    path: &Path,
+    width: u32,
+    height: u32,
+    depth: png::BitDepth,
+    metadata: &ImageMetadata,
+) -> Result<png::Encoder<'static, BufWriter<File>>, Box<dyn std::error::Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    for (key, value) in metadata.entries() {
+        encoder.add_itxt_chunk(key.to_string(), value)?;
+    }
+    Ok(encoder)
+}
+
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb: &[[u8; 3]],
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let encoder = png_encoder(path, width, height, png::BitDepth::Eight, metadata)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb.as_flattened())?;
+    Ok(())
+}
+
+/// Writes a 16-bit PNG, whose samples are stored big endian.
+pub fn write_png16(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb: &[[u16; 3]],
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let encoder = png_encoder(path, width, height, png::BitDepth::Sixteen, metadata)?;
+    let mut writer = encoder.write_header()?;
+    let bytes: Vec<u8> = rgb
+        .as_flattened()
+        .iter()
+        .flat_map(|c| c.to_be_bytes())
+        .collect();
+    writer.write_image_data(&bytes)?;
+    Ok(())
+}
+
+fn write_ppm_header(
+    file: &mut impl Write,
+    width: u32,
+    height: u32,
+    max_value: u16,
+    metadata: &ImageMetadata,
+) -> std::io::Result<()> {
+    writeln!(file, "P6")?;
+    for (key, value) in metadata.entries() {
+        // Comments end at the line break.
+        writeln!(file, "# {}: {}", key, value.replace(['\n', '\r'], " "))?;
+    }
+    write!(file, "{} {}\n{}\n", width, height, max_value)
+}
+
+pub fn write_ppm(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb: &[[u8; 3]],
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_ppm_header(&mut file, width, height, 255, metadata)?;
+    file.write_all(rgb.as_flattened())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes a 16-bit PPM, whose samples are stored big endian.
+pub fn write_ppm16(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb: &[[u16; 3]],
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_ppm_header(&mut file, width, height, u16::MAX, metadata)?;
+    for c in rgb.as_flattened() {
+        file.write_all(&c.to_be_bytes())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes a color PFM: little endian floats with the rows stored from the
+/// bottom up.
+pub fn write_pfm(
+    path: &Path,
+    width: u32,
+    height: u32,
+    radiance: &[[f32; 3]],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    // A negative scale marks little endian data.
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in radiance.chunks_exact(width as usize).rev() {
+        for c in row.as_flattened() {
+            file.write_all(&c.to_le_bytes())?;
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes an OpenEXR file with float32 R, G and B channels and the metadata
+/// as string attributes. Each of `aovs` adds a layer of channels named
+/// `<aov>.<channel>`, taken from the per pixel `features`.
+pub fn write_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    radiance: &[[f32; 3]],
+    features: &[Features],
+    aovs: &[Aov],
+    metadata: &ImageMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use exr::prelude::*;
+
+    let channel = |name: &str, samples: Vec<f32>| {
+        AnyChannel::new(Text::from(name), FlatSamples::F32(samples))
+    };
+    let mut channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = ["R", "G", "B"]
+        .iter()
+        .enumerate()
+        .map(|(c, name)| channel(name, radiance.iter().map(|px| px[c]).collect()))
+        .collect();
+    for aov in aovs {
+        for (c, name) in aov.channels().iter().enumerate() {
+            let samples = features.iter().map(|f| aov.values(f)[c]).collect();
+            channels.push(channel(&format!("{}.{}", aov.name(), name), samples));
+        }
+    }
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::default(),
+        AnyChannels::sort(channels),
+    );
+    let mut image = Image::from_layer(layer);
+    for (key, value) in metadata.entries() {
+        image
+            .attributes
+            .other
+            .insert(Text::from(key), AttributeValue::Text(exr_text(&value)));
+    }
+    image.write().to_file(path)?;
+    Ok(())
+}
+
+/// EXR text is Latin-1, so other characters are replaced.
+fn exr_text(value: &str) -> exr::meta::attribute::Text {
+    let latin1: String = value
+        .chars()
+        .map(|c| if (c as u32) < 256 { c } else { '?' })
+        .collect();
+    exr::meta::attribute::Text::new_or_panic(latin1)
+}
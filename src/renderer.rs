@@ -1,10 +1,16 @@
+use crate::adaptive::{AdaptiveBuffer, AdaptiveSettings};
+use crate::aov::Features;
+use crate::bsdf::Bsdf;
+use crate::bvh::Bvh;
 use crate::camera::Camera;
+use crate::filter::{Filter, FilterSampler};
+use crate::sampler::{Sampler, SamplerKind};
+use crate::sampling::power_heuristic;
 use crate::shape::Shape;
-use crate::types::{Hit, Light, Ray, find_first_hit};
+use crate::types::{Hit, Light, Ray};
 use glam::Vec3;
-use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
 use rayon::iter::IndexedParallelIterator;
+use rayon::iter::IntoParallelRefMutIterator;
 use rayon::iter::ParallelIterator;
 use rayon::slice::ParallelSliceMut;
 use std::fmt::Debug;
@@ -17,125 +23,397 @@ pub enum RenderMode {
     Pathtracing,
 }
 
+/// Options controlling how [`draw_frame`] renders a frame.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    pub render_mode: RenderMode,
+    /// Maximum number of reflection bounces in [`RenderMode::Raytrace`].
+    pub max_ray_depth: u32,
+    /// Maximum number of bounces in [`RenderMode::Pathtracing`]. Paths are
+    /// usually ended earlier by Russian roulette.
+    pub max_path_depth: u32,
+    /// Reconstruction filter used to distribute samples over each pixel.
+    pub filter: Filter,
+    /// Seed of the random numbers. Renders with the same seed, inputs and
+    /// sample indices produce bit-identical frame buffers.
+    pub seed: u64,
+    /// Source of the random numbers of each sample.
+    pub sampler: SamplerKind,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            render_mode: RenderMode::Raycast,
+            max_ray_depth: 5,
+            max_path_depth: 32,
+            filter: Filter::default(),
+            seed: 0,
+            sampler: SamplerKind::default(),
+        }
+    }
+}
+
+/// Number of samples per pixel each pass adds in `mode`.
+fn samples_per_pass(mode: RenderMode) -> u32 {
+    match mode {
+        RenderMode::Pathtracing => 10,
+        _ => 1,
+    }
+}
+
+/// Everything needed to compute the samples of a frame.
+struct FrameContext<'a> {
+    width: u32,
+    height: u32,
+    settings: &'a RenderSettings,
+    camera: &'a Camera,
+    light: &'a [Light],
+    scene: &'a Bvh,
+    filter: FilterSampler,
+}
+
+impl FrameContext<'_> {
+    /// Weighted radiance and first hit features of sample `sample` of pixel
+    /// (`x`, `y`), which belongs to the pass of `pass_len` samples starting
+    /// at `pass_start`.
+    fn sample(&self, (x, y): (u32, u32), sample: u32, pass: (u32, u32)) -> (Vec3, Features) {
+        let settings = self.settings;
+        // Every sample has its own stream, so the result does not depend on
+        // how rayon schedules the rows.
+        let mut sampler = Sampler::new(settings.sampler, settings.seed, (x, y), sample, pass);
+        let (offset, weight) = self.filter.sample(sampler.get_2d());
+        let Some(ray) = self.camera.generate_ray(
+            (x as f32 + 0.5 + offset.x) / self.width as f32,
+            (y as f32 + 0.5 + offset.y) / self.height as f32,
+            sampler.get_2d(),
+        ) else {
+            return (Vec3::ZERO, Features::default());
+        };
+
+        let best_hit = self.scene.intersect(&ray);
+        let features = Features::from_hit(&ray, best_hit.as_ref(), self.camera, self.scene);
+
+        let color = weight
+            * match settings.render_mode {
+                RenderMode::Normals => render_normals(best_hit),
+                RenderMode::Raycast => raycast(&ray, best_hit),
+                RenderMode::Raytrace => raytrace(
+                    self.light,
+                    self.scene,
+                    &ray,
+                    best_hit,
+                    settings.max_ray_depth,
+                ),
+                RenderMode::Pathtracing => pathtrace(
+                    self.light,
+                    self.scene,
+                    &ray,
+                    best_hit,
+                    settings.max_path_depth,
+                    &mut sampler,
+                ),
+            };
+        (color, features)
+    }
+}
+
+/// Adds one pass of samples to every pixel of `frame_buffer` and returns the
+/// number of samples per pixel added. `first_sample` is the number of samples
+/// accumulated so far, so that every pass draws fresh random numbers. If
+/// `features` is given, it receives the running mean of the first hit
+/// features of every pixel, the output variables of [`crate::aov`].
+#[allow(clippy::too_many_arguments)]
 pub fn draw_frame(
-    frame_buffer: &mut Vec<f32>,
+    frame_buffer: &mut [f32],
+    features: Option<&mut [Features]>,
     width: u32,
     height: u32,
-    render_mode: RenderMode,
+    first_sample: u32,
+    settings: &RenderSettings,
     camera: &Camera,
-    light: &Vec<Light>,
-    shapes: &Vec<Shape>,
+    light: &[Light],
+    scene: &Bvh,
 ) -> u32 {
-    let width = width as usize;
-    let height = height as usize;
+    let samples = samples_per_pass(settings.render_mode);
+    let context = FrameContext {
+        width,
+        height,
+        settings,
+        camera,
+        light,
+        scene,
+        filter: FilterSampler::new(&settings.filter),
+    };
 
-    let samples = match render_mode {
-        RenderMode::Pathtracing => 10,
-        _ => 1,
+    let mut feature_rows: Vec<Option<&mut [Features]>> = match features {
+        Some(features) => features.chunks_mut(width as usize).map(Some).collect(),
+        None => (0..height).map(|_| None).collect(),
     };
 
     frame_buffer
-        .par_chunks_mut(width * 3)
+        .par_chunks_mut(width as usize * 3)
+        .zip(feature_rows.par_iter_mut())
         .enumerate()
-        .for_each(|(y, row)| {
-            let mut rng: SmallRng = SmallRng::from_os_rng();
-
-            for x in 0..width {
-                let idx = x * 3;
-
-                let mut color = Vec3::new(0.0, 0.0, 0.0);
-                for _ in 0..samples {
-                    let ray =
-                        camera.generate_ray(x as f32 / width as f32, y as f32 / height as f32);
-
-                    let best_hit = find_first_hit(shapes.iter().map(|s| s.intersect(&ray)));
-
-                    color += match render_mode {
-                        RenderMode::Normals => render_normals(best_hit),
-                        RenderMode::Raycast => raycast(&camera, &ray, best_hit),
-                        RenderMode::Raytrace => raytrace(light, shapes, &ray, best_hit),
-                        RenderMode::Pathtracing => pathtrace(shapes, &ray, best_hit, &mut rng),
-                    };
+        .for_each(|(y, (row, feature_row))| {
+            for (x, px) in row.chunks_exact_mut(3).enumerate() {
+                let mut color = Vec3::ZERO;
+                for sample in first_sample..first_sample + samples {
+                    let (sample_color, sample_features) =
+                        context.sample((x as u32, y as u32), sample, (first_sample, samples));
+                    color += sample_color;
+                    if let Some(feature_row) = feature_row {
+                        feature_row[x].accumulate(&sample_features, sample);
+                    }
                 }
 
-                row[idx] += color.x;
-                row[idx + 1] += color.y;
-                row[idx + 2] += color.z;
+                px[0] += color.x;
+                px[1] += color.y;
+                px[2] += color.z;
             }
         });
     samples
 }
 
+/// Adds one pass of samples to the pixels of `buffer` that still need them
+/// according to `adaptive`, and returns the number of pixels sampled. The
+/// image is done once it returns 0.
+pub fn draw_adaptive_frame(
+    buffer: &mut AdaptiveBuffer,
+    settings: &RenderSettings,
+    adaptive: &AdaptiveSettings,
+    camera: &Camera,
+    light: &[Light],
+    scene: &Bvh,
+) -> usize {
+    let samples = samples_per_pass(settings.render_mode);
+    let context = FrameContext {
+        width: buffer.width,
+        height: buffer.height,
+        settings,
+        camera,
+        light,
+        scene,
+        filter: FilterSampler::new(&settings.filter),
+    };
+
+    buffer
+        .pixels
+        .par_chunks_mut(context.width as usize)
+        .enumerate()
+        .map(|(y, row)| {
+            let mut sampled = 0;
+            for (x, stats) in row.iter_mut().enumerate() {
+                if !stats.is_active(adaptive) {
+                    continue;
+                }
+                sampled += 1;
+                let first_sample = stats.sample_count;
+                // The last pass stops at the maximum number of samples
+                let samples = samples.min(adaptive.max_samples - first_sample);
+                for sample in first_sample..first_sample + samples {
+                    let (color, features) =
+                        context.sample((x as u32, y as u32), sample, (first_sample, samples));
+                    stats.features.accumulate(&features, sample);
+                    stats.add_sample(color);
+                }
+            }
+            sampled
+        })
+        .sum()
+}
+
 fn render_normals(best_hit: Option<Hit>) -> Vec3 {
     best_hit.map_or(Vec3::new(0.0, 0.0, 0.0), |hit| {
         hit.normal + Vec3::new(1.0, 1.0, 1.0)
     })
 }
-fn raycast(camera: &Camera, ray: &Ray, best_hit: Option<Hit>) -> Vec3 {
+fn raycast(ray: &Ray, best_hit: Option<Hit>) -> Vec3 {
     best_hit.map_or(Vec3::new(0.0, 0.0, 0.0), |hit| {
-        let l = (camera.pos - hit.point(&ray)).normalize();
+        // Headlight shading, lit from the direction of the viewer.
+        let l = -ray.direction;
         let brightness = l.dot(hit.normal).max(0.0);
-        hit.material.ambient * hit.material.color
+        hit.material.emission
+            + hit.material.ambient * hit.material.color
             + (1.0 - hit.material.ambient) * brightness * hit.material.color
     })
 }
 
-fn raytrace(light: &Vec<Light>, shapes: &Vec<Shape>, ray: &Ray, best_hit: Option<Hit>) -> Vec3 {
+/// Whitted style ray tracing: emission, ambient, Lambert and Blinn-Phong
+/// terms for every visible light, plus mirror reflection weighted by
+/// `reflection` and dielectric refraction weighted by `transmission`,
+/// followed for at most `depth` bounces.
+fn raytrace(light: &[Light], scene: &Bvh, ray: &Ray, best_hit: Option<Hit>, depth: u32) -> Vec3 {
     const ORIGIN_BIAS: f32 = 1e-4;
     const BLACK: Vec3 = Vec3::new(0.0, 0.0, 0.0);
 
-    best_hit.map_or(Vec3::new(0.0, 0.0, 0.0), |hit| {
-        hit.material.ambient * hit.material.color
+    best_hit.map_or(BLACK, |hit| {
+        let material = &hit.material;
+        let p = hit.point(ray) + hit.normal * ORIGIN_BIAS;
+        let local = material.emission
+            + material.ambient * material.color
             + light
                 .iter()
                 .map(|l| {
-                    let mut p = hit.point(&ray) + hit.normal * ORIGIN_BIAS;
                     let distance = (p - l.position).length();
                     let light_ray = Ray {
                         origin: p,
                         direction: (l.position - p) / distance,
                     };
-
-                    find_first_hit(shapes.iter().map(|s| s.intersect(&light_ray)))
-                        .filter(|h| h.t > ORIGIN_BIAS && h.t < distance - ORIGIN_BIAS)
-                        .map_or_else(
-                            || {
-                                let light = light_ray.direction.dot(hit.normal).max(0.0) * l.color;
-                                (1.0 - hit.material.ambient) * light * hit.material.color
-                            },
-                            |_| BLACK,
-                        )
+                    // Faces turned away from the light get neither diffuse
+                    // nor specular light.
+                    let cos_light = light_ray.direction.dot(hit.normal);
+                    if cos_light <= 0.0
+                        || scene.occluded(&light_ray, ORIGIN_BIAS, distance - ORIGIN_BIAS)
+                    {
+                        return BLACK;
+                    }
+                    let diffuse = cos_light * l.color;
+                    let half_vector = (light_ray.direction - ray.direction).normalize();
+                    let specular = half_vector
+                        .dot(hit.normal)
+                        .max(0.0)
+                        .powf(material.shininess)
+                        * l.color;
+                    (1.0 - material.ambient) * diffuse * material.color
+                        + material.specular_coef * specular
                 })
                 .reduce(|a, b| a + b)
-                .unwrap_or(BLACK)
+                .unwrap_or(BLACK);
+
+        if depth == 0 || (material.reflection <= 0.0 && material.transmission <= 0.0) {
+            return local;
+        }
+
+        let interface = dielectric_interface(ray.direction, hit.normal, material.ior);
+        let point = hit.point(ray);
+        let trace = |origin: Vec3, direction: Vec3| {
+            let secondary = Ray { origin, direction };
+            raytrace(
+                light,
+                scene,
+                &secondary,
+                scene.intersect(&secondary),
+                depth - 1,
+            )
+        };
+        let reflected = trace(
+            point + interface.normal * ORIGIN_BIAS,
+            reflect(ray.direction, interface.normal),
+        );
+        let opaque = (1.0 - material.reflection) * local + material.reflection * reflected;
+        if material.transmission <= 0.0 {
+            return opaque;
+        }
+
+        let dielectric = match interface.refracted {
+            Some(refracted) => {
+                let transmitted = trace(point - interface.normal * ORIGIN_BIAS, refracted);
+                interface.reflectance * reflected
+                    + (1.0 - interface.reflectance) * material.color * transmitted
+            }
+            None => reflected,
+        };
+        (1.0 - material.transmission) * opaque + material.transmission * dielectric
     })
 }
 
-fn pathtrace(shapes: &Vec<Shape>, ray: &Ray, best_hit: Option<Hit>, rng: &mut SmallRng) -> Vec3 {
+/// Number of path tracing bounces before Russian roulette kicks in.
+const ROULETTE_DEPTH: u32 = 3;
+
+/// Unidirectional path tracing with next-event estimation: at every opaque
+/// vertex one point light or emissive shape is sampled directly and tested
+/// with a shadow ray, and the BSDF sampled direction continues the path.
+/// Both estimates of the light from emissive shapes are combined with
+/// multiple importance sampling. After [`ROULETTE_DEPTH`] bounces paths are
+/// terminated by Russian roulette, and never exceed `max_depth` bounces.
+fn pathtrace(
+    light: &[Light],
+    scene: &Bvh,
+    ray: &Ray,
+    best_hit: Option<Hit>,
+    max_depth: u32,
+    sampler: &mut Sampler,
+) -> Vec3 {
     best_hit.map_or(Vec3::new(0.0, 0.0, 0.0), |hit| {
+        let light_count = light.len() + scene.emitters().len();
         let mut ray_light = Vec3::new(1.0, 1.0, 1.0);
         let mut incoming_light = Vec3::new(0.0, 0.0, 0.0);
         let mut cur_hit = hit;
         let mut cur_ray = *ray;
-        for _ in 0..5 {
-            let mut new_d = sample_random_on_sphere(rng);
-            let cos_n_d = new_d.dot(cur_hit.normal);
-            if cos_n_d < 0.0 {
-                new_d -= 2.0 * cos_n_d * cur_hit.normal; //new_d.reflect(h.normal)
-            }
+        // Density of the BSDF sample that led to `cur_hit`, `None` for camera
+        // rays and specular bounces, which light sampling cannot produce.
+        let mut bsdf_pdf: Option<f32> = None;
+        for depth in 0..=max_depth {
+            // Emitters also reflect, so the path continues after them. The
+            // ambient fill term of the other modes has no place here.
+            let emission_weight = match bsdf_pdf {
+                Some(pdf) if scene.is_emitter(cur_hit.shape_index) => {
+                    let shape = &scene.shapes()[cur_hit.shape_index];
+                    let light_pdf =
+                        emitter_pdf(shape, cur_hit.normal, cur_ray.direction, cur_hit.t)
+                            / light_count as f32;
+                    power_heuristic(pdf, light_pdf)
+                }
+                _ => 1.0,
+            };
+            incoming_light += emission_weight * ray_light * cur_hit.material.emission;
 
-            if (cur_hit.material.ambient > 0.0) {
-                incoming_light += ray_light * cur_hit.material.ambient * cur_hit.material.color;
+            if depth == max_depth {
                 break;
             }
-            ray_light *= cur_hit.material.color * new_d.dot(cur_hit.normal) * 2.0;
+            // Continue with a probability following the throughput and
+            // compensate the survivors, which keeps the estimate unbiased.
+            if depth >= ROULETTE_DEPTH {
+                let survival = ray_light.max_element().min(1.0);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                ray_light /= survival;
+            }
+
+            let material = cur_hit.material;
+            let point = cur_hit.point(&cur_ray);
+            cur_ray = if material.transmission > 0.0 && sampler.get_1d() < material.transmission {
+                bsdf_pdf = None;
+                let interface =
+                    dielectric_interface(cur_ray.direction, cur_hit.normal, material.ior);
+                match interface.refracted {
+                    Some(refracted) if sampler.get_1d() >= interface.reflectance => {
+                        ray_light *= material.color;
+                        Ray {
+                            origin: point - interface.normal * 0.001,
+                            direction: refracted,
+                        }
+                    }
+                    _ => Ray {
+                        origin: point + interface.normal * 0.001,
+                        direction: reflect(cur_ray.direction, interface.normal),
+                    },
+                }
+            } else {
+                let bsdf = Bsdf::new(&material, cur_hit.normal, cur_ray.direction);
+                incoming_light +=
+                    ray_light * sample_direct_light(light, scene, point, &bsdf, sampler);
 
-            let new_origin = cur_hit.point(&cur_ray) + cur_hit.normal * 0.001;
-            cur_ray = Ray {
-                origin: new_origin,
-                direction: new_d,
+                let u_lobe = sampler.get_1d();
+                let Some(direction) = bsdf.sample(u_lobe, sampler.get_2d()) else {
+                    break;
+                };
+                let pdf = bsdf.pdf(direction);
+                // Directions on the horizon carry no light and would divide
+                // zero by zero.
+                if pdf <= 0.0 {
+                    break;
+                }
+                ray_light *= bsdf.eval(direction) * direction.dot(bsdf.normal()) / pdf;
+                bsdf_pdf = Some(pdf);
+                Ray {
+                    origin: point + bsdf.normal() * 0.001,
+                    direction,
+                }
             };
-            cur_hit = match find_first_hit(shapes.iter().map(|s| s.intersect(&cur_ray))) {
+            cur_hit = match scene.intersect(&cur_ray) {
                 Some(h) => h,
                 None => break,
             };
@@ -144,11 +422,119 @@ fn pathtrace(shapes: &Vec<Shape>, ray: &Ray, best_hit: Option<Hit>, rng: &mut Sm
     })
 }
 
-pub fn sample_random_on_sphere(rng: &mut SmallRng) -> Vec3 {
-    //z: latitude of the sphere
-    let z: f32 = rng.random_range(-1.0..=1.0);
-    let phi: f32 = rng.random_range(0.0..=std::f32::consts::TAU);
-    // Convert spherical to Cartesian.
-    let r_xy = (1.0f32 - z * z).sqrt(); // circle radius at latitude z
-    Vec3::new(r_xy * phi.cos(), r_xy * phi.sin(), z)
+/// Light reflected by `bsdf` at `point` from one point light or emissive
+/// shape chosen uniformly at random, divided by the probability of the
+/// choice. Point lights use their color as intensity, falling off with the
+/// squared distance.
+fn sample_direct_light(
+    light: &[Light],
+    scene: &Bvh,
+    point: Vec3,
+    bsdf: &Bsdf,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    let emitters = scene.emitters();
+    let light_count = light.len() + emitters.len();
+    if light_count == 0 {
+        return Vec3::ZERO;
+    }
+
+    // Point lights can only be reached by light sampling, so only emissive
+    // shapes get a solid angle density for weighting against the BSDF.
+    let choice = sampler.get_index(light_count);
+    let (target, radiance, light_pdf) = if let Some(l) = light.get(choice) {
+        (
+            l.position,
+            l.color / (l.position - point).length_squared(),
+            None,
+        )
+    } else {
+        let shape = &scene.shapes()[emitters[choice - light.len()]];
+        let u_select = sampler.get_1d();
+        let Some(sample) = shape.sample_surface(u_select, sampler.get_2d()) else {
+            return Vec3::ZERO;
+        };
+        let offset = sample.point - point;
+        let distance = offset.length();
+        let pdf = emitter_pdf(shape, sample.normal, offset / distance, distance);
+        (sample.point, shape.material().emission / pdf, Some(pdf))
+    };
+
+    let offset = target - point;
+    let distance = offset.length();
+    let direction = offset / distance;
+    let cos = direction.dot(bsdf.normal());
+    if cos <= 0.0 || !radiance.is_finite() {
+        return Vec3::ZERO;
+    }
+    let shadow_ray = Ray {
+        origin: point + bsdf.normal() * 0.001,
+        direction,
+    };
+    if scene.occluded(&shadow_ray, 0.0, distance * (1.0 - 1e-3)) {
+        return Vec3::ZERO;
+    }
+    let weight = light_pdf.map_or(1.0, |pdf| {
+        power_heuristic(pdf / light_count as f32, bsdf.pdf(direction))
+    });
+    weight * bsdf.eval(direction) * radiance * cos * light_count as f32
+}
+
+/// Solid angle density of sampling the point at `distance` along
+/// `direction` on the emissive `shape`, whose normal there is `normal`.
+fn emitter_pdf(shape: &Shape, normal: Vec3, direction: Vec3, distance: f32) -> f32 {
+    shape.surface_pdf(normal) * distance * distance / normal.dot(direction).abs()
+}
+
+fn reflect(direction: Vec3, normal: Vec3) -> Vec3 {
+    direction - 2.0 * direction.dot(normal) * normal
+}
+
+/// The surface normal flipped to face against the incoming `direction`.
+fn facing_normal(direction: Vec3, normal: Vec3) -> Vec3 {
+    if direction.dot(normal) > 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+/// Result of a ray hitting the boundary of a dielectric.
+struct DielectricInterface {
+    /// Surface normal on the side of the incoming ray.
+    normal: Vec3,
+    /// Refracted direction, `None` on total internal reflection.
+    refracted: Option<Vec3>,
+    /// Fraction of the light reflected (Schlick's approximation of Fresnel).
+    reflectance: f32,
+}
+
+/// Applies Snell's law at a surface with outward `normal` separating air from
+/// a medium with index of refraction `ior`; rays hitting the surface from
+/// behind leave the medium.
+fn dielectric_interface(direction: Vec3, normal: Vec3, ior: f32) -> DielectricInterface {
+    let entering = direction.dot(normal) < 0.0;
+    let normal = facing_normal(direction, normal);
+    let eta = if entering { 1.0 / ior } else { ior };
+
+    let cos_i = -direction.dot(normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return DielectricInterface {
+            normal,
+            refracted: None,
+            reflectance: 1.0,
+        };
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let refracted = (eta * direction + (eta * cos_i - cos_t) * normal).normalize();
+
+    // Schlick uses the angle on the optically thinner side.
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    let cos = if entering { cos_i } else { cos_t };
+    DielectricInterface {
+        normal,
+        refracted: Some(refracted),
+        reflectance: r0 + (1.0 - r0) * (1.0 - cos).powi(5),
+    }
 }
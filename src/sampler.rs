@@ -0,0 +1,253 @@
+//! Sources of the uniform random numbers used for pixel, lens, light and
+//! BSDF sampling.
+//!
+//! A [`Sampler`] produces the numbers of one sample of one pixel as a
+//! sequence of dimensions, consumed one or two at a time. Besides independent
+//! random numbers it implements well distributed sequences which converge
+//! faster over the samples of a pixel:
+//!
+//! - stratified: Latin hypercube samples over the samples of each pass,
+//! - Halton: the radical inverse in one prime base per dimension, shifted by
+//!   a random offset per pixel (Cranley-Patterson rotation),
+//! - Sobol: the first two Sobol dimensions with hash based Owen scrambling
+//!   and shuffling per dimension (Burley 2020, "Practical Hash-based Owen
+//!   Scrambling").
+
+use glam::Vec2;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::sampling::hash;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "independent" => SamplerKind::Independent,
+            "stratified" => SamplerKind::Stratified,
+            "halton" => SamplerKind::Halton,
+            "sobol" => SamplerKind::Sobol,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+}
+
+/// Largest `f32` below 1.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Uniform random numbers for one sample of one pixel.
+pub struct Sampler {
+    kind: SamplerKind,
+    /// Hash of the seed and the pixel, shared by all samples of the pixel.
+    pixel_hash: u64,
+    /// Index of the sample within the pixel.
+    sample: u32,
+    /// First sample and sample count of the current pass.
+    pass_start: u32,
+    pass_len: u32,
+    dimension: u32,
+    /// Independent numbers, also used where a sequence runs out of
+    /// dimensions.
+    rng: SmallRng,
+}
+
+impl Sampler {
+    /// Sampler for sample `sample` of pixel (`x`, `y`), which belongs to the
+    /// pass of `pass_len` samples starting at `pass_start`.
+    pub fn new(
+        kind: SamplerKind,
+        seed: u64,
+        (x, y): (u32, u32),
+        sample: u32,
+        (pass_start, pass_len): (u32, u32),
+    ) -> Self {
+        Sampler {
+            kind,
+            pixel_hash: hash(seed, &[x, y]),
+            sample,
+            pass_start,
+            pass_len,
+            dimension: 0,
+            rng: SmallRng::seed_from_u64(hash(seed, &[x, y, sample])),
+        }
+    }
+
+    pub fn get_1d(&mut self) -> f32 {
+        let dimension = self.next_dimensions(1);
+        let u = match self.kind {
+            SamplerKind::Independent => None,
+            SamplerKind::Stratified => Some(self.stratified(dimension)),
+            SamplerKind::Halton => self.halton(dimension),
+            SamplerKind::Sobol => Some(self.sobol(dimension, 0)),
+        };
+        u.unwrap_or_else(|| self.rng.random())
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    /// Two numbers from consecutive dimensions, well distributed jointly.
+    pub fn get_2d(&mut self) -> Vec2 {
+        let dimension = self.next_dimensions(2);
+        let u = match self.kind {
+            SamplerKind::Independent => None,
+            SamplerKind::Stratified => Some(Vec2::new(
+                self.stratified(dimension),
+                self.stratified(dimension + 1),
+            )),
+            SamplerKind::Halton => self
+                .halton(dimension)
+                .zip(self.halton(dimension + 1))
+                .map(Vec2::from),
+            SamplerKind::Sobol => Some(Vec2::new(
+                self.sobol(dimension, 0),
+                self.sobol(dimension, 1),
+            )),
+        };
+        u.unwrap_or_else(|| Vec2::new(self.rng.random(), self.rng.random()))
+            .min(Vec2::splat(ONE_MINUS_EPSILON))
+    }
+
+    /// Uniform choice among `count` options.
+    pub fn get_index(&mut self, count: usize) -> usize {
+        ((self.get_1d() * count as f32) as usize).min(count - 1)
+    }
+
+    fn next_dimensions(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+
+    /// A random permutation of the strata per pass and dimension gives every
+    /// sample of the pass its own stratum in each dimension.
+    fn stratified(&mut self, dimension: u32) -> f32 {
+        let seed = hash(self.pixel_hash, &[self.pass_start, dimension]) as u32;
+        let stratum = permute(self.sample - self.pass_start, self.pass_len, seed);
+        (stratum as f32 + self.rng.random::<f32>()) / self.pass_len as f32
+    }
+
+    fn halton(&self, dimension: u32) -> Option<f32> {
+        let base = *PRIMES.get(dimension as usize)?;
+        let shift = hash_to_unit(hash(self.pixel_hash, &[dimension]));
+        Some((radical_inverse(self.sample, base) + shift).fract())
+    }
+
+    /// Component `component` of the Sobol point for `dimension`, which pairs
+    /// with the other component of the same call.
+    fn sobol(&self, dimension: u32, component: usize) -> f32 {
+        let seed = hash(self.pixel_hash, &[dimension]);
+        // Shuffling the index per dimension decorrelates the dimensions.
+        let index = nested_uniform_scramble(self.sample, seed as u32);
+        let scramble_seed = hash(seed, &[component as u32]) as u32;
+        let bits = nested_uniform_scramble(sobol(index, component), scramble_seed);
+        bits as f32 * 2f32.powi(-32)
+    }
+}
+
+fn hash_to_unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 * 2f32.powi(-24)
+}
+
+/// Mirrors the digits of `index` in `base` around the radix point.
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0u64;
+    while index > 0 {
+        reversed = reversed * base as u64 + (index % base) as u64;
+        index /= base;
+        inv_base_n *= inv_base;
+    }
+    (reversed as f64 * inv_base_n) as f32
+}
+
+/// Element `i` of a pseudo random permutation of `0..len` selected by
+/// `seed` (Kensler 2013, "Correlated Multi-Jittered Sampling").
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            return (i.wrapping_add(seed)) % len;
+        }
+    }
+}
+
+/// Point `index` of the first two dimensions of the Sobol sequence as 32
+/// bit fractions.
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    for bit in 0..32 {
+        if index >> bit & 1 != 0 {
+            result ^= if dimension == 0 {
+                1 << (31 - bit)
+            } else {
+                direction
+            };
+        }
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling of the bits of `x` from the most significant one down.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Hash in which every bit only depends on the bits below it, which turns
+/// into an Owen scramble on reversed bits.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
@@ -0,0 +1,81 @@
+//! Warping functions turning uniform random numbers in `[0, 1)` into samples
+//! of geometric distributions.
+
+use glam::{Vec2, Vec3};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
+
+/// Two unit vectors completing `n` to a right handed orthonormal basis
+/// (Duff et al. 2017, "Building an Orthonormal Basis, Revisited").
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// Maps the unit square to the unit disk preserving stratification
+/// (Shirley and Chiu's concentric mapping).
+pub fn sample_concentric_disk(u: Vec2) -> Vec2 {
+    let offset = 2.0 * u - Vec2::ONE;
+    if offset == Vec2::ZERO {
+        return Vec2::ZERO;
+    }
+    let (r, theta) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, FRAC_PI_4 * (offset.y / offset.x))
+    } else {
+        (offset.y, FRAC_PI_2 - FRAC_PI_4 * (offset.x / offset.y))
+    };
+    r * Vec2::new(theta.cos(), theta.sin())
+}
+
+/// Uniformly distributed point in the regular polygon with `sides` corners
+/// on the unit circle, the first one on the x axis.
+pub fn sample_regular_polygon(u: Vec2, sides: u32) -> Vec2 {
+    // Pick one of the triangles fanning out from the center, then reuse the
+    // remainder of `u.x` within it.
+    let scaled = u.x * sides as f32;
+    let side = (scaled as u32).min(sides - 1);
+    let u = Vec2::new(scaled - side as f32, u.y);
+    let corner = |i: u32| {
+        let angle = TAU * i as f32 / sides as f32;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    let su = u.x.sqrt();
+    su * ((1.0 - u.y) * corner(side) + u.y * corner(side + 1))
+}
+
+/// Direction in the hemisphere around `normal` distributed proportionally to
+/// the cosine with the normal, i.e. with density `cos θ / π`.
+pub fn sample_cosine_hemisphere(u: Vec2, normal: Vec3) -> Vec3 {
+    let d = sample_concentric_disk(u);
+    let z = (1.0 - d.length_squared()).max(0.0).sqrt();
+    let (t, b) = orthonormal_basis(normal);
+    (d.x * t + d.y * b + z * normal).normalize()
+}
+
+/// Multiple importance sampling weight of a sample drawn with density `pdf`
+/// against another strategy with density `other_pdf` (Veach's power
+/// heuristic with exponent 2).
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// Hashes `values` into `seed`, for deriving independent random streams
+/// per pixel, sample or dimension.
+pub fn hash(seed: u64, values: &[u32]) -> u64 {
+    values
+        .iter()
+        .fold(mix64(seed), |hash, &value| mix64(hash ^ value as u64))
+}
+
+/// The SplitMix64 finalizer, a cheap bijective 64 bit hash.
+fn mix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
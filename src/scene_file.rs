@@ -0,0 +1,614 @@
+//! Text scene description format.
+//!
+//! A scene file is a sequence of blocks. Whitespace and line breaks are not
+//! significant and `#` starts a comment that runs to the end of the line.
+//!
+//! ```text
+//! camera {
+//!     position 0 -7 0.5
+//!     look_at 0 0 0
+//!     up 0 0 1
+//!     fov 53.1
+//! }
+//!
+//! light {
+//!     position 0 -0.75 1.8
+//!     color 1 1 1
+//! }
+//!
+//! material white {
+//!     color 0.9 0.9 0.9
+//!     ambient 0.1
+//! }
+//!
+//! sphere { center -1.2 -0.2 0.5  radius 0.66  material white }
+//!
+//! transform {
+//!     translate 0 0.25 -1.24
+//!     rotate_z 30
+//!     scale 0.75
+//!     box { material white }
+//! }
+//! ```
+//!
+//! Blocks:
+//!
+//! * `camera` (exactly one): `position`, `look_at`, `up` (vectors) and `fov`
+//!   (degrees, measured along `fov_axis`: `vertical` (default) or
+//!   `horizontal`), optionally a thin lens with `aperture` (radius, default
+//!   `0` for a pinhole), `focus_distance` (default: distance to `look_at`)
+//!   and `blades` (polygonal aperture with that many sides, at least 3;
+//!   default `0` for round), and `projection`: `perspective` (default),
+//!   `orthographic`, `fisheye <fov>` (equidistant, fov in degrees) or
+//!   `equirectangular`.
+//! * `light`: `position` and `color` (vectors).
+//! * `material <name>`: `color` (vector, default `1 1 1`), `emission`
+//!   (vector, default `0 0 0`), `ambient`, `reflection`, `shininess`,
+//!   `specular_coef` and `transmission` (numbers, default `0`) and `ior`
+//!   (number, default `1`). Materials must be defined before they are
+//!   referenced.
+//! * Shapes, each with a `material <name>` property:
+//!   * `box` – the unit box spanning `[-1, 1]` on every axis,
+//!   * `sphere` – `center` (vector) and `radius`,
+//!   * `plane` – `normal` (vector) and `d`, the plane being `normal · p = d`,
+//!   * `cylinder` – unit radius, from `z = 0` to `z = 1`,
+//!   * `cone` – unit base radius at `z = 0`, apex at `z = 1`,
+//!   * `triangle` – three `vertex x y z` properties, counter-clockwise,
+//!   * `mesh` – any number of `vertex x y z`, optionally one `normal x y z`
+//!     per vertex for smooth shading, and at least one `face a b c` triangle
+//!     indexing the vertices from zero.
+//! * `obj` – `file <path>` loads a Wavefront OBJ model (see [`crate::obj`]),
+//!   with paths relative to the scene file and without spaces. The optional
+//!   `material <name>` is used for faces without an MTL material.
+//! * `transform`: any number of `translate x y z`, `scale s` or `scale x y z`
+//!   and `rotate_x|rotate_y|rotate_z <degrees>` operations followed by exactly
+//!   one shape, which may be another `transform`. Operations compose in the
+//!   order written, so `translate`, `rotate_z`, `scale` yields `T * R * S`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+
+use crate::camera::{Camera, FovAxis, Projection};
+use crate::obj::load_obj;
+use crate::shape::{Mesh, Shape};
+use crate::types::{Light, Material, Transform};
+
+/// Camera, lights and shapes making up a scene.
+pub type SceneDescription = (Camera, Vec<Light>, Vec<Shape>);
+
+/// Error raised while parsing a scene file, pointing at the offending token.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reads and parses the scene file at `path`.
+pub fn load_scene_file(path: &Path) -> Result<SceneDescription, Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_scene(&source, base_dir).map_err(|e| format!("{}:{}", path.display(), e).into())
+}
+
+/// Parses a scene description, see the module documentation for the format.
+/// Files referenced by the scene are resolved relative to `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<SceneDescription, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source),
+        pos: 0,
+        materials: HashMap::new(),
+        base_dir,
+    };
+
+    let mut camera = None;
+    let mut lights = Vec::new();
+    let mut shapes = Vec::new();
+
+    while let Some(token) = parser.peek() {
+        match token.text {
+            "camera" => {
+                let token = parser.next_token()?;
+                if camera.is_some() {
+                    return Err(token.error("camera is defined more than once"));
+                }
+                camera = Some(parser.camera()?);
+            }
+            "light" => {
+                parser.next_token()?;
+                lights.push(parser.light()?);
+            }
+            "material" => {
+                parser.next_token()?;
+                parser.material()?;
+            }
+            _ => shapes.extend(parser.shapes()?),
+        }
+    }
+
+    let camera = camera.ok_or_else(|| parser.end_error("scene has no camera"))?;
+    Ok((camera, lights, shapes))
+}
+
+#[derive(Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+            let is_brace = c == '{' || c == '}';
+            if c.is_whitespace() || is_brace {
+                if let Some(s) = start.take() {
+                    tokens.push(Token {
+                        text: &line[s..i],
+                        line: line_idx + 1,
+                        column: line[..s].chars().count() + 1,
+                    });
+                }
+                if is_brace {
+                    tokens.push(Token {
+                        text: &line[i..i + 1],
+                        line: line_idx + 1,
+                        column: line[..i].chars().count() + 1,
+                    });
+                }
+            } else if start.is_none() {
+                start = Some(i);
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    materials: HashMap<&'a str, Material>,
+    base_dir: &'a Path,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn end_error(&self, message: impl Into<String>) -> ParseError {
+        let (line, column) = self
+            .tokens
+            .last()
+            .map_or((1, 1), |t| (t.line, t.column + t.text.chars().count()));
+        ParseError {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token<'a>, ParseError> {
+        let token = self
+            .peek()
+            .ok_or_else(|| self.end_error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token<'a>, ParseError> {
+        let token = self.next_token()?;
+        if token.text == text {
+            Ok(token)
+        } else {
+            Err(token.error(format!("expected '{}', found '{}'", text, token.text)))
+        }
+    }
+
+    fn name(&mut self) -> Result<Token<'a>, ParseError> {
+        let token = self.next_token()?;
+        if token.text == "{" || token.text == "}" {
+            Err(token.error(format!("expected a name, found '{}'", token.text)))
+        } else {
+            Ok(token)
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, ParseError> {
+        let token = self.next_token()?;
+        token
+            .text
+            .parse()
+            .map_err(|_| token.error(format!("expected a number, found '{}'", token.text)))
+    }
+
+    fn vector(&mut self) -> Result<Vec3, ParseError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// Iterates over the `key value...` properties of a `{ ... }` block,
+    /// handing each key to `property` which consumes the values.
+    fn block(
+        &mut self,
+        mut property: impl FnMut(&mut Self, Token<'a>) -> Result<(), ParseError>,
+    ) -> Result<Token<'a>, ParseError> {
+        let open = self.expect("{")?;
+        loop {
+            let key = self.peek().ok_or_else(|| open.error("unclosed '{'"))?;
+            self.pos += 1;
+            match key.text {
+                "}" => return Ok(open),
+                "{" => return Err(key.error("unexpected '{'")),
+                _ => property(self, key)?,
+            }
+        }
+    }
+
+    fn material_ref(&mut self) -> Result<Material, ParseError> {
+        let token = self.name()?;
+        self.materials
+            .get(token.text)
+            .copied()
+            .ok_or_else(|| token.error(format!("unknown material '{}'", token.text)))
+    }
+
+    fn camera(&mut self) -> Result<Camera, ParseError> {
+        let (mut position, mut look_at, mut up, mut fov) = (None, None, None, None);
+        let (mut aperture, mut focus_distance, mut blades) = (0.0, None, 0u32);
+        let mut projection = Projection::Perspective;
+        let mut fov_axis = FovAxis::Vertical;
+        let open = self.block(|p, key| {
+            match key.text {
+                "position" => position = Some(p.vector()?),
+                "look_at" => look_at = Some(p.vector()?),
+                "up" => up = Some(p.vector()?),
+                "fov" => fov = Some(p.number()?),
+                "aperture" => aperture = p.number()?,
+                "focus_distance" => focus_distance = Some(p.number()?),
+                "blades" => {
+                    let token = p.next_token()?;
+                    blades = match token.text.parse() {
+                        Ok(n @ (0 | 3..)) => n,
+                        _ => {
+                            return Err(token.error(format!(
+                                "expected 0 or at least 3 blades, found '{}'",
+                                token.text
+                            )));
+                        }
+                    };
+                }
+                "fov_axis" => {
+                    let axis = p.name()?;
+                    fov_axis = match axis.text {
+                        "horizontal" => FovAxis::Horizontal,
+                        "vertical" => FovAxis::Vertical,
+                        _ => return Err(axis.error(format!("unknown fov axis '{}'", axis.text))),
+                    };
+                }
+                "projection" => {
+                    let kind = p.name()?;
+                    projection = match kind.text {
+                        "perspective" => Projection::Perspective,
+                        "orthographic" => Projection::Orthographic,
+                        "fisheye" => Projection::Fisheye {
+                            fov: p.number()?.to_radians(),
+                        },
+                        "equirectangular" => Projection::Equirectangular,
+                        _ => {
+                            return Err(kind.error(format!("unknown projection '{}'", kind.text)));
+                        }
+                    };
+                }
+                _ => return Err(unknown_property(key, "camera")),
+            }
+            Ok(())
+        })?;
+        let missing = |name| open.error(format!("camera is missing '{}'", name));
+        let mut camera = Camera::new(
+            position.ok_or_else(|| missing("position"))?,
+            look_at.ok_or_else(|| missing("look_at"))?,
+            up.ok_or_else(|| missing("up"))?,
+            fov.ok_or_else(|| missing("fov"))?,
+        );
+        camera.aperture_radius = aperture;
+        camera.focus_distance = focus_distance.unwrap_or(camera.focus_distance);
+        camera.aperture_blades = blades;
+        camera.projection = projection;
+        camera.fov_axis = fov_axis;
+        Ok(camera)
+    }
+
+    fn light(&mut self) -> Result<Light, ParseError> {
+        let mut light = Light {
+            position: Vec3::ZERO,
+            color: Vec3::ONE,
+        };
+        self.block(|p, key| {
+            match key.text {
+                "position" => light.position = p.vector()?,
+                "color" => light.color = p.vector()?,
+                _ => return Err(unknown_property(key, "light")),
+            }
+            Ok(())
+        })?;
+        Ok(light)
+    }
+
+    fn material(&mut self) -> Result<(), ParseError> {
+        let name = self.name()?;
+        if self.materials.contains_key(name.text) {
+            return Err(name.error(format!("material '{}' is defined twice", name.text)));
+        }
+        let mut material = default_material();
+        self.block(|p, key| {
+            match key.text {
+                "color" => material.color = p.vector()?,
+                "ambient" => material.ambient = p.number()?,
+                "emission" => material.emission = p.vector()?,
+                "reflection" => material.reflection = p.number()?,
+                "shininess" => material.shininess = p.number()?,
+                "specular_coef" => material.specular_coef = p.number()?,
+                "ior" => material.ior = p.number()?,
+                "transmission" => material.transmission = p.number()?,
+                _ => return Err(unknown_property(key, "material")),
+            }
+            Ok(())
+        })?;
+        self.materials.insert(name.text, material);
+        Ok(())
+    }
+
+    /// Parses a shape block, which for `obj` and transforms of it can yield
+    /// several shapes.
+    fn shapes(&mut self) -> Result<Vec<Shape>, ParseError> {
+        match self.peek().map(|t| t.text) {
+            Some("transform") => {
+                self.pos += 1;
+                self.transform()
+            }
+            Some("obj") => {
+                self.pos += 1;
+                self.obj()
+            }
+            _ => Ok(vec![self.shape()?]),
+        }
+    }
+
+    fn shape(&mut self) -> Result<Shape, ParseError> {
+        let kind = self.name()?;
+        match kind.text {
+            "box" | "cylinder" | "cone" => {
+                let mut material = None;
+                let open = self.block(|p, key| match key.text {
+                    "material" => {
+                        material = Some(p.material_ref()?);
+                        Ok(())
+                    }
+                    _ => Err(unknown_property(key, kind.text)),
+                })?;
+                let material = material.ok_or_else(|| missing_material(open, kind))?;
+                Ok(match kind.text {
+                    "box" => Shape::UnitBox { material },
+                    "cylinder" => Shape::Cylinder { material },
+                    _ => Shape::Cone { material },
+                })
+            }
+            "sphere" => {
+                let (mut center, mut radius, mut material) = (Vec3::ZERO, 1.0, None);
+                let open = self.block(|p, key| {
+                    match key.text {
+                        "center" => center = p.vector()?,
+                        "radius" => radius = p.number()?,
+                        "material" => material = Some(p.material_ref()?),
+                        _ => return Err(unknown_property(key, "sphere")),
+                    }
+                    Ok(())
+                })?;
+                Ok(Shape::Sphere {
+                    center,
+                    radius,
+                    material: material.ok_or_else(|| missing_material(open, kind))?,
+                })
+            }
+            "plane" => {
+                let (mut normal, mut d, mut material) = (Vec3::Z, 0.0, None);
+                let open = self.block(|p, key| {
+                    match key.text {
+                        "normal" => normal = p.vector()?,
+                        "d" => d = p.number()?,
+                        "material" => material = Some(p.material_ref()?),
+                        _ => return Err(unknown_property(key, "plane")),
+                    }
+                    Ok(())
+                })?;
+                Ok(Shape::Plane {
+                    normal,
+                    d,
+                    material: material.ok_or_else(|| missing_material(open, kind))?,
+                })
+            }
+            "triangle" => {
+                let (mut vertices, mut material) = (Vec::new(), None);
+                let open = self.block(|p, key| {
+                    match key.text {
+                        "vertex" if vertices.len() < 3 => vertices.push(p.vector()?),
+                        "vertex" => return Err(key.error("triangle has more than 3 vertices")),
+                        "material" => material = Some(p.material_ref()?),
+                        _ => return Err(unknown_property(key, "triangle")),
+                    }
+                    Ok(())
+                })?;
+                let vertices = <[Vec3; 3]>::try_from(vertices)
+                    .map_err(|_| open.error("triangle needs 3 vertices"))?;
+                Ok(Shape::Triangle {
+                    vertices,
+                    material: material.ok_or_else(|| missing_material(open, kind))?,
+                })
+            }
+            "mesh" => self.mesh(kind),
+            other => Err(kind.error(format!("unknown block '{}'", other))),
+        }
+    }
+
+    fn mesh(&mut self, kind: Token<'a>) -> Result<Shape, ParseError> {
+        let (mut positions, mut normals, mut faces, mut material) =
+            (Vec::new(), Vec::new(), Vec::new(), None);
+        let open = self.block(|p, key| {
+            match key.text {
+                "vertex" => positions.push(p.vector()?),
+                "normal" => normals.push(p.vector()?),
+                "face" => {
+                    let mut face = [0u32; 3];
+                    for index in &mut face {
+                        let token = p.next_token()?;
+                        *index = token.text.parse().map_err(|_| {
+                            token.error(format!("expected a vertex index, found '{}'", token.text))
+                        })?;
+                    }
+                    faces.push((key, face));
+                }
+                "material" => material = Some(p.material_ref()?),
+                _ => return Err(unknown_property(key, "mesh")),
+            }
+            Ok(())
+        })?;
+        if faces.is_empty() {
+            return Err(open.error("mesh has no faces"));
+        }
+        if let Some((token, _)) = faces
+            .iter()
+            .find(|(_, face)| face.iter().any(|&i| i as usize >= positions.len()))
+        {
+            return Err(token.error("face references a missing vertex"));
+        }
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(open.error("mesh needs one normal per vertex"));
+        }
+        let material = material.ok_or_else(|| missing_material(open, kind))?;
+        let normals = (!normals.is_empty()).then_some(normals);
+        let indices = faces.into_iter().map(|(_, face)| face).collect();
+        Ok(Shape::Mesh {
+            mesh: Arc::new(Mesh::new(positions, indices, normals)),
+            material,
+        })
+    }
+
+    fn obj(&mut self) -> Result<Vec<Shape>, ParseError> {
+        let (mut file, mut material) = (None, default_material());
+        let open = self.block(|p, key| {
+            match key.text {
+                "file" => file = Some(p.name()?),
+                "material" => material = p.material_ref()?,
+                _ => return Err(unknown_property(key, "obj")),
+            }
+            Ok(())
+        })?;
+        let file = file.ok_or_else(|| open.error("obj is missing 'file'"))?;
+        load_obj(&self.base_dir.join(file.text), material)
+            .map_err(|e| file.error(format!("cannot load obj: {}", e)))
+    }
+
+    fn transform(&mut self) -> Result<Vec<Shape>, ParseError> {
+        let open = self.expect("{")?;
+        let mut matrix = Mat4::IDENTITY;
+        let mut shape = None;
+        loop {
+            let token = self.peek().ok_or_else(|| open.error("unclosed '{'"))?;
+            let op = match token.text {
+                "}" => {
+                    self.pos += 1;
+                    break;
+                }
+                "translate" | "scale" | "rotate_x" | "rotate_y" | "rotate_z" => {
+                    self.pos += 1;
+                    token.text
+                }
+                _ => {
+                    if shape.is_some() {
+                        return Err(token.error("transform contains more than one shape"));
+                    }
+                    shape = Some(self.shapes()?);
+                    continue;
+                }
+            };
+            if shape.is_some() {
+                return Err(token.error("transform operations must precede the shape"));
+            }
+            matrix *= match op {
+                "translate" => Mat4::from_translation(self.vector()?),
+                "scale" => {
+                    let x = self.number()?;
+                    let is_uniform = self.peek().is_none_or(|t| t.text.parse::<f32>().is_err());
+                    if is_uniform {
+                        Mat4::from_scale(Vec3::splat(x))
+                    } else {
+                        Mat4::from_scale(Vec3::new(x, self.number()?, self.number()?))
+                    }
+                }
+                "rotate_x" => Mat4::from_rotation_x(self.number()?.to_radians()),
+                "rotate_y" => Mat4::from_rotation_y(self.number()?.to_radians()),
+                _ => Mat4::from_rotation_z(self.number()?.to_radians()),
+            };
+        }
+        let shapes = shape.ok_or_else(|| open.error("transform contains no shape"))?;
+        // Small uniform scales have tiny determinants but are fine; only a
+        // singular matrix has no finite inverse.
+        if matrix.determinant() == 0.0 || !matrix.inverse().is_finite() {
+            return Err(open.error("transform is not invertible"));
+        }
+        Ok(shapes
+            .into_iter()
+            .map(|shape| Shape::TransformedShape {
+                shape: Box::new(shape),
+                transform: Transform::new(matrix),
+            })
+            .collect())
+    }
+}
+
+fn default_material() -> Material {
+    Material {
+        color: Vec3::ONE,
+        ambient: 0.0,
+        reflection: 0.0,
+        shininess: 0.0,
+        specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
+    }
+}
+
+fn unknown_property(key: Token<'_>, block: &str) -> ParseError {
+    key.error(format!("unknown {} property '{}'", block, key.text))
+}
+
+fn missing_material(open: Token<'_>, kind: Token<'_>) -> ParseError {
+    open.error(format!("{} is missing 'material'", kind.text))
+}
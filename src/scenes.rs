@@ -5,13 +5,35 @@ use crate::camera::Camera;
 use crate::shape::Shape;
 use crate::types::{Light, Material, Transform};
 
+/// Builds one of the built-in scenes by number:
+/// 0 (default), 1 (cornell), 2 (axes), 3 (cylinder+plane).
+pub fn load_scene(scene: u8) -> (Camera, Vec<Light>, Vec<Shape>) {
+    match scene {
+        1 => make_cornell_scene(),
+        2 => make_axes_scene(),
+        3 => make_scene_cylinder_plane(),
+        _ => make_default_scene(),
+    }
+}
+
+/// Maps a scene name (or its number) to the index used by [`load_scene`].
+pub fn scene_index(name: &str) -> Option<u8> {
+    match name {
+        "0" | "default" => Some(0),
+        "1" | "cornell" => Some(1),
+        "2" | "axes" => Some(2),
+        "3" | "cylinder" => Some(3),
+        _ => None,
+    }
+}
+
 // Scene builders
 pub fn make_default_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
     let camera = Camera::new(
         Vec3::new(0.0, -5.0, -1.25),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        1.1,
+        57.6,
     );
     let light = vec![Light {
         position: Vec3::new(2.0, -2.0, 3.0),
@@ -24,6 +46,9 @@ pub fn make_default_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let red = Material {
@@ -32,6 +57,9 @@ pub fn make_default_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let shapes: Vec<Shape> = vec![
@@ -51,7 +79,7 @@ pub fn make_scene_with_eight_boxes() -> (Camera, Vec<Light>, Vec<Shape>) {
         Vec3::new(0.0, -15.0, 5.),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        1.1,
+        57.6,
     );
     let light = vec![Light {
         position: Vec3::new(3.0, -2.0, 4.0),
@@ -64,6 +92,9 @@ pub fn make_scene_with_eight_boxes() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let red = Material {
         color: Vec3::new(1.0, 0.0, 0.0),
@@ -71,6 +102,9 @@ pub fn make_scene_with_eight_boxes() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let mut shapes: Vec<Shape> = vec![Shape::Plane {
@@ -98,7 +132,7 @@ pub fn make_scene_cylinder_plane() -> (Camera, Vec<Light>, Vec<Shape>) {
         Vec3::new(0.0, -5.0, -0.75),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        1.1,
+        57.6,
     );
     let light = vec![Light {
         position: Vec3::new(2.0, -2.0, 3.0),
@@ -111,6 +145,9 @@ pub fn make_scene_cylinder_plane() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let red = Material {
@@ -119,6 +156,9 @@ pub fn make_scene_cylinder_plane() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let shapes: Vec<Shape> = vec![
@@ -137,7 +177,7 @@ pub fn make_cornell_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         Vec3::new(0.0, -7.0, 0.5),
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        1.0,
+        53.1,
     );
     let light = vec![
         Light {
@@ -160,13 +200,19 @@ pub fn make_cornell_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let white_light = Material {
         color: Vec3::splat(0.9),
-        ambient: 1.0,
+        ambient: 0.0,
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::splat(4.0),
     };
     let red = Material {
         color: Vec3::new(0.9, 0.1, 0.1),
@@ -174,6 +220,9 @@ pub fn make_cornell_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let green = Material {
         color: Vec3::new(0.1, 0.9, 0.1),
@@ -181,14 +230,9 @@ pub fn make_cornell_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
-    };
-
-    let blue = Material {
-        color: Vec3::new(0.1, 0.1, 0.9),
-        ambient: 0.0,
-        reflection: 0.0,
-        shininess: 0.0,
-        specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let shapes: Vec<Shape> = vec![
@@ -262,7 +306,7 @@ pub fn make_axes_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         Vec3::new(1.5, 4.0, 1.35),
         Vec3::new(0.5, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        1.1,
+        57.6,
     );
     let light = vec![Light {
         position: Vec3::new(2.0, 4.0, 3.0),
@@ -275,6 +319,9 @@ pub fn make_axes_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let green = Material {
         color: Vec3::new(0.0, 1.0, 0.0),
@@ -282,6 +329,9 @@ pub fn make_axes_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let blue = Material {
         color: Vec3::new(0.0, 0.0, 1.0),
@@ -289,6 +339,9 @@ pub fn make_axes_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
     let white = Material {
         color: Vec3::splat(0.9),
@@ -296,6 +349,9 @@ pub fn make_axes_scene() -> (Camera, Vec<Light>, Vec<Shape>) {
         reflection: 0.0,
         shininess: 0.0,
         specular_coef: 0.0,
+        ior: 1.0,
+        transmission: 0.0,
+        emission: Vec3::ZERO,
     };
 
     let shaft_r = 0.05f32;
@@ -0,0 +1,135 @@
+use glam::{Vec2, Vec3};
+
+use crate::bvh::BvhTree;
+use crate::shape::SurfaceSample;
+use crate::shape::triangle::{intersect_triangle, sample_triangle, triangle_normal};
+use crate::types::{Aabb, Hit, Material, Ray};
+
+/// Indexed triangle mesh with an internal hierarchy over its triangles.
+///
+/// Meshes are shared between shapes through an `Arc`, so the same vertex and
+/// index buffers can be instanced with different transforms and materials.
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    indices: Vec<[u32; 3]>,
+    bounds: Aabb,
+    tree: BvhTree,
+    /// Running sum of the triangle areas, for sampling by area.
+    area_cdf: Vec<f32>,
+}
+
+impl Mesh {
+    /// Creates a mesh from counter-clockwise triangles indexing `positions`.
+    ///
+    /// With `normals` (one per position) the shading normal is interpolated
+    /// across each triangle, otherwise the faceted geometric normal is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of range or the normal count does not match
+    /// the position count.
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[u32; 3]>, normals: Option<Vec<Vec3>>) -> Self {
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "mesh index out of range"
+        );
+        if let Some(normals) = &normals {
+            assert_eq!(normals.len(), positions.len(), "one normal per position");
+        }
+
+        let triangle_bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|tri| {
+                tri.iter()
+                    .fold(Aabb::EMPTY, |acc, &i| acc.grow(positions[i as usize]))
+            })
+            .collect();
+        let bounds = triangle_bounds
+            .iter()
+            .fold(Aabb::EMPTY, |acc, b| acc.union(b));
+
+        let area_cdf = indices
+            .iter()
+            .scan(0.0, |total, tri| {
+                let vertices = tri.map(|i| positions[i as usize]);
+                *total += 0.5 * triangle_normal(vertices).length();
+                Some(*total)
+            })
+            .collect();
+
+        Mesh {
+            tree: BvhTree::build(&triangle_bounds),
+            area_cdf,
+            positions,
+            normals,
+            indices,
+            bounds,
+        }
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3]> {
+        self.normals.as_deref()
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn triangle(&self, index: usize) -> [Vec3; 3] {
+        self.indices[index].map(|i| self.positions[i as usize])
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Samples a point uniformly with respect to the surface area, choosing
+    /// the triangle with `u_select`.
+    pub fn sample_surface(&self, u_select: f32, u: Vec2) -> SurfaceSample {
+        let area = self.surface_area();
+        let tri = self
+            .area_cdf
+            .partition_point(|&a| a <= u_select * area)
+            .min(self.indices.len().saturating_sub(1));
+        let vertices = self.triangle(tri);
+        SurfaceSample {
+            point: sample_triangle(vertices, u),
+            normal: triangle_normal(vertices).normalize(),
+            pdf: 1.0 / area,
+        }
+    }
+
+    pub fn intersect(&self, ray: &Ray, material: &Material) -> Option<Hit> {
+        let mut best = None;
+        self.tree.traverse(ray, f32::INFINITY, false, |tri, t_max| {
+            let (t, barycentric) = intersect_triangle(ray, self.triangle(tri))?;
+            (t < t_max).then(|| {
+                best = Some((t, tri, barycentric));
+                t
+            })
+        });
+
+        best.map(|(t, tri, barycentric)| {
+            let normal = match &self.normals {
+                Some(normals) => {
+                    let [n0, n1, n2] = self.indices[tri].map(|i| normals[i as usize]);
+                    barycentric.x * n0 + barycentric.y * n1 + barycentric.z * n2
+                }
+                None => triangle_normal(self.triangle(tri)),
+            };
+            Hit::new(t, normal.normalize(), *material)
+        })
+    }
+}
@@ -1,8 +1,18 @@
 use glam::Vec3;
-use std::array::IntoIter;
+use std::sync::Arc;
 
-use crate::types::{Hit, Material, Ray, Transform, Transformable, find_first_hit};
+use crate::types::{Aabb, Hit, Material, Ray, Transform, Transformable, find_first_hit};
 
+mod mesh;
+mod sample;
+mod triangle;
+
+pub use mesh::Mesh;
+pub use sample::SurfaceSample;
+pub use triangle::intersect_triangle;
+use triangle::triangle_normal;
+
+#[allow(clippy::enum_variant_names)]
 pub enum Shape {
     UnitBox {
         material: Material,
@@ -23,6 +33,14 @@ pub enum Shape {
     Cone {
         material: Material,
     },
+    Triangle {
+        vertices: [Vec3; 3],
+        material: Material,
+    },
+    Mesh {
+        mesh: Arc<Mesh>,
+        material: Material,
+    },
     TransformedShape {
         shape: Box<Shape>,
         transform: Transform,
@@ -30,6 +48,30 @@ pub enum Shape {
 }
 
 impl Shape {
+    /// Bounds of the shape in its parent coordinate system, `None` for
+    /// unbounded shapes such as planes.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Shape::TransformedShape { shape, transform } => shape
+                .bounding_box()
+                .map(|bounds| bounds.transformed(transform)),
+            Shape::UnitBox { .. } => Some(Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0))),
+            Shape::Sphere { center, radius, .. } => Some(Aabb::new(
+                center - Vec3::splat(radius.abs()),
+                center + Vec3::splat(radius.abs()),
+            )),
+            Shape::Plane { .. } => None,
+            Shape::Cylinder { .. } | Shape::Cone { .. } => Some(Aabb::new(
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+            )),
+            Shape::Triangle { vertices, .. } => {
+                Some(vertices.iter().fold(Aabb::EMPTY, |acc, &v| acc.grow(v)))
+            }
+            Shape::Mesh { mesh, .. } => Some(mesh.bounding_box()),
+        }
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
         match self {
             Shape::TransformedShape { shape, transform } => {
@@ -136,6 +178,9 @@ impl Shape {
                 intersect_cap_with_radius_one(ray, 0.0, Vec3::new(0.0, 0.0, -1.0), material),
                 intersect_cone_infinite(ray, material).filter(test_if_hits_between_0_1(ray)),
             ]),
+            Shape::Triangle { vertices, material } => intersect_triangle(ray, *vertices)
+                .map(|(t, _)| Hit::new(t, triangle_normal(*vertices).normalize(), *material)),
+            Shape::Mesh { mesh, material } => mesh.intersect(ray, material),
         }
     }
 }
@@ -0,0 +1,170 @@
+use glam::{Vec2, Vec3};
+use std::f32::consts::{PI, TAU};
+
+use crate::shape::Shape;
+use crate::shape::triangle::{sample_triangle, triangle_normal};
+use crate::types::{Material, Transform};
+
+/// A point sampled on the surface of a shape.
+#[derive(Copy, Clone, Debug)]
+pub struct SurfaceSample {
+    pub point: Vec3,
+    /// Unit surface normal at `point`.
+    pub normal: Vec3,
+    /// Probability density of the sample with respect to surface area.
+    pub pdf: f32,
+}
+
+impl Shape {
+    /// Material of the shape, looking through transforms.
+    pub fn material(&self) -> &Material {
+        match self {
+            Shape::TransformedShape { shape, .. } => shape.material(),
+            Shape::UnitBox { material }
+            | Shape::Sphere { material, .. }
+            | Shape::Plane { material, .. }
+            | Shape::Cylinder { material }
+            | Shape::Cone { material }
+            | Shape::Triangle { material, .. }
+            | Shape::Mesh { material, .. } => material,
+        }
+    }
+
+    /// Whether [`Shape::sample_surface`] supports the shape. Planes are
+    /// infinite; cylinders and cones are not sampled, and neither are meshes
+    /// without area.
+    pub fn is_sampleable(&self) -> bool {
+        match self {
+            Shape::TransformedShape { shape, .. } => shape.is_sampleable(),
+            Shape::UnitBox { .. } | Shape::Sphere { .. } | Shape::Triangle { .. } => true,
+            Shape::Mesh { mesh, .. } => mesh.surface_area() > 0.0,
+            Shape::Plane { .. } | Shape::Cylinder { .. } | Shape::Cone { .. } => false,
+        }
+    }
+
+    /// Samples a point on the surface from the uniform random numbers
+    /// `u_select` and `u`, used to sample emissive shapes as light sources.
+    /// Returns `None` for shapes that are not [sampleable](Shape::is_sampleable).
+    pub fn sample_surface(&self, u_select: f32, u: Vec2) -> Option<SurfaceSample> {
+        match self {
+            Shape::TransformedShape { shape, transform } => match **shape {
+                // Boxes pick faces by their transformed area, which keeps
+                // flattened boxes such as panel lights from wasting samples.
+                Shape::UnitBox { .. } => Some(sample_unit_box(u_select, u, Some(transform))),
+                _ => shape.sample_surface(u_select, u).map(|local| {
+                    let point = transform
+                        .local_to_global(local.point.extend(1.0))
+                        .truncate();
+                    SurfaceSample {
+                        point,
+                        normal: transform.local_normal_to_global(local.normal),
+                        pdf: local.pdf / transform.area_scale(local.normal),
+                    }
+                }),
+            },
+            Shape::UnitBox { .. } => Some(sample_unit_box(u_select, u, None)),
+            Shape::Sphere { center, radius, .. } => {
+                let normal = sample_uniform_sphere(u);
+                Some(SurfaceSample {
+                    point: center + radius * normal,
+                    normal,
+                    pdf: 1.0 / (4.0 * PI * radius * radius),
+                })
+            }
+            Shape::Triangle { vertices, .. } => {
+                let normal = triangle_normal(*vertices);
+                Some(SurfaceSample {
+                    point: sample_triangle(*vertices, u),
+                    normal: normal.normalize(),
+                    pdf: 2.0 / normal.length(),
+                })
+            }
+            Shape::Mesh { mesh, .. } if mesh.surface_area() > 0.0 => {
+                Some(mesh.sample_surface(u_select, u))
+            }
+            Shape::Mesh { .. }
+            | Shape::Plane { .. }
+            | Shape::Cylinder { .. }
+            | Shape::Cone { .. } => None,
+        }
+    }
+
+    /// Area density with which [`Shape::sample_surface`] generates a point
+    /// with unit normal `normal`. All shapes are sampled uniformly by area,
+    /// so only transforms make the density depend on the point.
+    pub fn surface_pdf(&self, normal: Vec3) -> f32 {
+        match self {
+            Shape::TransformedShape { shape, transform } => match **shape {
+                Shape::UnitBox { .. } => 1.0 / unit_box_area(Some(transform)),
+                _ => {
+                    let local = transform.global_normal_to_local(normal);
+                    shape.surface_pdf(local) / transform.area_scale(local)
+                }
+            },
+            Shape::UnitBox { .. } => 1.0 / unit_box_area(None),
+            Shape::Sphere { radius, .. } => 1.0 / (4.0 * PI * radius * radius),
+            Shape::Triangle { vertices, .. } => 2.0 / triangle_normal(*vertices).length(),
+            Shape::Mesh { mesh, .. } => 1.0 / mesh.surface_area(),
+            Shape::Plane { .. } | Shape::Cylinder { .. } | Shape::Cone { .. } => 0.0,
+        }
+    }
+}
+
+/// Uniformly distributed direction on the unit sphere.
+fn sample_uniform_sphere(u: Vec2) -> Vec3 {
+    let z = 1.0 - 2.0 * u.x;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = TAU * u.y;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+const AXES: [Vec3; 3] = [Vec3::X, Vec3::Y, Vec3::Z];
+
+/// Areas of the two unit box faces orthogonal to each axis after applying
+/// `transform`.
+fn unit_box_face_areas(transform: Option<&Transform>) -> [f32; 3] {
+    AXES.map(|axis| 4.0 * transform.map_or(1.0, |t| t.area_scale(axis)))
+}
+
+fn unit_box_area(transform: Option<&Transform>) -> f32 {
+    2.0 * unit_box_face_areas(transform).iter().sum::<f32>()
+}
+
+/// Samples the faces of the unit box, uniformly with respect to the area
+/// after applying `transform`.
+fn sample_unit_box(u_select: f32, u: Vec2, transform: Option<&Transform>) -> SurfaceSample {
+    let face_areas = unit_box_face_areas(transform);
+    let total_area = unit_box_area(transform);
+
+    // Pick one of the six faces proportionally to its area.
+    let mut target = u_select * total_area;
+    let mut face = 5;
+    for (i, &area) in face_areas.iter().flat_map(|a| [a, a]).enumerate() {
+        if target < area {
+            face = i;
+            break;
+        }
+        target -= area;
+    }
+
+    let axis = face / 2;
+    let side = if face % 2 == 0 { 1.0 } else { -1.0 };
+    let mut point = Vec3::ZERO;
+    point[axis] = side;
+    point[(axis + 1) % 3] = 2.0 * u.x - 1.0;
+    point[(axis + 2) % 3] = 2.0 * u.y - 1.0;
+    let normal = side * AXES[axis];
+
+    match transform {
+        Some(t) => SurfaceSample {
+            point: t.local_to_global(point.extend(1.0)).truncate(),
+            normal: t.local_normal_to_global(normal),
+            pdf: 1.0 / total_area,
+        },
+        None => SurfaceSample {
+            point,
+            normal,
+            pdf: 1.0 / total_area,
+        },
+    }
+}
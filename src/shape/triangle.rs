@@ -0,0 +1,73 @@
+use glam::{Vec2, Vec3};
+
+use crate::types::Ray;
+
+/// Watertight ray/triangle intersection (Woop, Benthin, Wald 2013).
+///
+/// Returns the ray distance and the barycentric weights of the three
+/// vertices. Rays passing exactly through a shared edge or vertex hit one of
+/// the adjacent triangles, so meshes have no cracks.
+pub fn intersect_triangle(ray: &Ray, vertices: [Vec3; 3]) -> Option<(f32, Vec3)> {
+    // Permute the axes so that the ray direction is largest along z.
+    let kz = ray.direction.abs().max_position();
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if ray.direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear so that the ray points along +z.
+    let sx = ray.direction[kx] / ray.direction[kz];
+    let sy = ray.direction[ky] / ray.direction[kz];
+    let sz = 1.0 / ray.direction[kz];
+
+    let [a, b, c] = vertices.map(|v| v - ray.origin);
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    // Scaled barycentric coordinates, falling back to double precision on edges.
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        let edge = |px: f32, py: f32, qx: f32, qy: f32| {
+            (px as f64 * qy as f64 - py as f64 * qx as f64) as f32
+        };
+        u = edge(cx, cy, bx, by);
+        v = edge(ax, ay, cx, cy);
+        w = edge(bx, by, ax, ay);
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t_scaled = u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz];
+    if (det < 0.0 && t_scaled >= 0.0) || (det > 0.0 && t_scaled <= 0.0) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some((t_scaled * inv_det, Vec3::new(u, v, w) * inv_det))
+}
+
+/// Unnormalized geometric normal following the counter-clockwise winding.
+pub fn triangle_normal(vertices: [Vec3; 3]) -> Vec3 {
+    (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0])
+}
+
+/// Uniformly distributed point on the triangle.
+pub fn sample_triangle(vertices: [Vec3; 3], u: Vec2) -> Vec3 {
+    let su = u.x.sqrt();
+    let b0 = 1.0 - su;
+    let b1 = u.y * su;
+    b0 * vertices[0] + b1 * vertices[1] + (1.0 - b0 - b1) * vertices[2]
+}
@@ -0,0 +1,138 @@
+//! Tone mapping: turning accumulated linear radiance into display values.
+//!
+//! The average radiance of each pixel is scaled by the exposure, compressed
+//! into `[0, 1]` by a tone mapping operator and encoded with the sRGB
+//! transfer function.
+
+use glam::Vec3;
+
+/// Curve compressing exposed linear radiance into `[0, 1]`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    /// Clips at 1.
+    Linear,
+    /// `c / (1 + c)`, which never reaches white.
+    Reinhard,
+    /// Reinhard scaled so that the radiance `white` maps to 1.
+    ExtendedReinhard { white: f32 },
+    /// Narkowicz's fit of the ACES filmic reference rendering transform.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "linear" => ToneMapOperator::Linear,
+            "reinhard" => ToneMapOperator::Reinhard,
+            "extended-reinhard" => ToneMapOperator::ExtendedReinhard { white: 4.0 },
+            "aces" => ToneMapOperator::Aces,
+            "hable" => ToneMapOperator::Hable,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Linear => "linear",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::ExtendedReinhard { .. } => "extended-reinhard",
+            ToneMapOperator::Aces => "aces",
+            ToneMapOperator::Hable => "hable",
+        }
+    }
+
+    pub fn apply(&self, c: Vec3) -> Vec3 {
+        let c = c.max(Vec3::ZERO);
+        match *self {
+            ToneMapOperator::Linear => c,
+            ToneMapOperator::Reinhard => c / (Vec3::ONE + c),
+            ToneMapOperator::ExtendedReinhard { white } => {
+                c * (Vec3::ONE + c / (white * white)) / (Vec3::ONE + c)
+            }
+            ToneMapOperator::Aces => {
+                let (a, b, c2, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (c * (a * c + b)) / (c * (c2 * c + d) + e)
+            }
+            ToneMapOperator::Hable => {
+                const WHITE: f32 = 11.2;
+                let curve = |x: Vec3| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                curve(2.0 * c) / curve(Vec3::splat(WHITE))
+            }
+        }
+        .min(Vec3::ONE)
+    }
+}
+
+/// Options of [`tone_map`].
+#[derive(Copy, Clone, Debug)]
+pub struct ToneMapSettings {
+    pub operator: ToneMapOperator,
+    /// Exposure in stops. With `auto_exposure` it is a compensation on top
+    /// of the automatic exposure.
+    pub exposure: f32,
+    /// Expose for the log-average luminance of the image.
+    pub auto_exposure: bool,
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        ToneMapSettings {
+            operator: ToneMapOperator::Aces,
+            exposure: 0.0,
+            auto_exposure: true,
+        }
+    }
+}
+
+/// Luminance of linear sRGB primaries.
+pub fn luminance(c: Vec3) -> f32 {
+    c.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Scale that maps the log-average luminance of `radiance` to middle gray
+/// (Reinhard et al. 2002, "Photographic Tone Reproduction").
+pub fn auto_exposure(radiance: &[[f32; 3]]) -> f32 {
+    const MIDDLE_GRAY: f32 = 0.18;
+    // Keeps black pixels from dragging the logarithm to minus infinity.
+    const DELTA: f32 = 1e-4;
+    if radiance.is_empty() {
+        return 1.0;
+    }
+    let log_sum: f64 = radiance
+        .iter()
+        .map(|&px| (DELTA + luminance(Vec3::from(px)).max(0.0)).ln() as f64)
+        .sum();
+    let log_average = (log_sum / radiance.len() as f64).exp() as f32;
+    MIDDLE_GRAY / log_average
+}
+
+/// The sRGB opto-electronic transfer function, encoding linear values in
+/// `[0, 1]` for display.
+pub fn srgb_oetf(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Tone maps average linear radiance per pixel to sRGB encoded display
+/// values in `[0, 1]`.
+pub fn tone_map(radiance: &[[f32; 3]], settings: &ToneMapSettings) -> Vec<[f32; 3]> {
+    let mut scale = 2f32.powf(settings.exposure);
+    if settings.auto_exposure {
+        scale *= auto_exposure(radiance);
+    }
+    radiance
+        .iter()
+        .map(|&px| {
+            let mapped = settings.operator.apply(scale * Vec3::from(px));
+            mapped.to_array().map(srgb_oetf)
+        })
+        .collect()
+}
@@ -1,4 +1,8 @@
-use glam::{Mat4, Vec3, Vec4};
+use glam::{Mat3, Mat4, Vec3, Vec4};
+
+mod aabb;
+
+pub use aabb::Aabb;
 
 pub trait Transformable {
     fn to_local_coordinates(&self, transform: &Transform) -> Self;
@@ -11,15 +15,25 @@ pub struct Ray {
     pub direction: Vec3,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Material {
     pub color: Vec3,
     pub ambient: f32,
     pub reflection: f32,
     pub shininess: f32,
     pub specular_coef: f32,
+    /// Index of refraction of the material relative to the surrounding air.
+    pub ior: f32,
+    /// Fraction of the light that passes through the surface as through a
+    /// dielectric, split by Fresnel into reflected and refracted parts.
+    pub transmission: f32,
+    /// Radiance emitted by the surface, independent of its reflectance and
+    /// not limited to 1.0.
+    pub emission: Vec3,
 }
 
+/// A point light. The path tracer treats `color` as its intensity, falling
+/// off with the squared distance.
 #[derive(Copy, Clone, Debug)]
 pub struct Light {
     pub position: Vec3,
@@ -30,6 +44,8 @@ pub struct Hit {
     pub t: f32,
     pub normal: Vec3,
     pub material: Material,
+    /// Index of the hit top-level shape, filled in by [`crate::bvh::Bvh`].
+    pub shape_index: usize,
 }
 
 impl Hit {
@@ -38,6 +54,7 @@ impl Hit {
             t,
             normal,
             material,
+            shape_index: 0,
         }
     }
     pub fn point(&self, ray: &Ray) -> Vec3 {
@@ -46,8 +63,8 @@ impl Hit {
 }
 
 pub fn find_first_hit(shape_iterator: impl IntoIterator<Item = Option<Hit>>) -> Option<Hit> {
-    shape_iterator.into_iter().filter_map(|s| s).min_by(|x, y| {
-        if (x.t < y.t) {
+    shape_iterator.into_iter().flatten().min_by(|x, y| {
+        if x.t < y.t {
             std::cmp::Ordering::Less
         } else {
             std::cmp::Ordering::Greater
@@ -67,13 +84,24 @@ impl Transformable for Ray {
         }
     }
     fn to_global_coordinates(&self, transform: &Transform) -> Self {
-        todo!()
+        Ray {
+            origin: transform
+                .local_to_global(self.origin.extend(1.0))
+                .truncate(),
+            direction: transform
+                .local_to_global(self.direction.extend(0.0))
+                .truncate(),
+        }
     }
 }
 
 impl Transformable for Hit {
     fn to_local_coordinates(&self, transform: &Transform) -> Self {
-        todo!()
+        Hit::new(
+            self.t,
+            transform.global_normal_to_local(self.normal),
+            self.material,
+        )
     }
     fn to_global_coordinates(&self, transform: &Transform) -> Self {
         Hit::new(
@@ -104,9 +132,21 @@ impl Transform {
     pub fn local_to_global(&self, v: Vec4) -> Vec4 {
         self.matrix * v
     }
+    pub fn global_normal_to_local(&self, n: Vec3) -> Vec3 {
+        (self.matrix.transpose() * n.extend(0.0))
+            .truncate()
+            .normalize()
+    }
     pub fn local_normal_to_global(&self, n: Vec3) -> Vec3 {
         (self.inverse.transpose() * n.extend(0.0))
             .truncate()
             .normalize()
     }
+
+    /// Factor by which the transform scales the area of a local surface
+    /// element with unit normal `n`.
+    pub fn area_scale(&self, n: Vec3) -> f32 {
+        let linear = Mat3::from_mat4(self.matrix);
+        linear.determinant().abs() * (linear.inverse().transpose() * n).length()
+    }
 }